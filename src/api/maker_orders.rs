@@ -0,0 +1,105 @@
+use super::solve::{Error, H160Wrapper};
+use crate::models::settlement_contract_data::{OrderCancellation, OrderUid, SignedOrder};
+use crate::order_book::OrderBook;
+use contracts::MooSettlementContract;
+use serde::de::DeserializeOwned;
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::{
+    hyper::StatusCode,
+    reply::{self, json, with_status},
+    Filter, Rejection, Reply,
+};
+use web3::types::H160;
+
+fn submit(
+    order_book: Arc<OrderBook>,
+    contract: MooSettlementContract,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("maker_orders")
+        .and(warp::post())
+        .and(extract_payload())
+        .and_then(move |signed: SignedOrder| {
+            let order_book = order_book.clone();
+            let contract = contract.clone();
+            async move {
+                let uid = signed.order.uid.clone();
+                let response = match order_book.submit(signed, &contract).await {
+                    Ok(()) => {
+                        tracing::info!(?uid, "maker order submitted");
+                        with_status(json(&uid), StatusCode::CREATED)
+                    }
+                    Err(err) => with_status(
+                        json(&Error {
+                            error_type: "InvalidOrder",
+                            description: &err.to_string(),
+                        }),
+                        StatusCode::BAD_REQUEST,
+                    ),
+                };
+                Result::<_, Infallible>::Ok(response)
+            }
+        })
+}
+
+fn list(
+    order_book: Arc<OrderBook>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let all = warp::path!("maker_orders").map(|| None);
+    // `H160Wrapper` wraps the `primitive-types` address, not the web3 one.
+    let of_maker =
+        warp::path!("maker_orders" / H160Wrapper).map(|maker: H160Wrapper| Some(H160(maker.0 .0)));
+    warp::get()
+        .and(all.or(of_maker).unify())
+        .map(move |maker| reply::json(&order_book.orders(maker)))
+}
+
+fn cancel(
+    order_book: Arc<OrderBook>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("maker_orders" / OrderUid)
+        .and(warp::delete())
+        .and(extract_payload())
+        .map(move |uid: OrderUid, cancellation: OrderCancellation| {
+            match order_book.cancel(&uid, &cancellation.signature.0) {
+                Ok(Some(cancelled)) => {
+                    tracing::info!(%uid, "maker order cancelled");
+                    with_status(json(&cancelled), StatusCode::OK)
+                }
+                Ok(None) => with_status(
+                    json(&Error {
+                        error_type: "NotFound",
+                        description: "no maker order with this uid",
+                    }),
+                    StatusCode::NOT_FOUND,
+                ),
+                Err(err) => with_status(
+                    json(&Error {
+                        error_type: "InvalidSignature",
+                        description: &err.to_string(),
+                    }),
+                    StatusCode::FORBIDDEN,
+                ),
+            }
+        })
+}
+
+/// Maker orders and cancellations are small, unlike auctions.
+const MAX_JSON_BODY_PAYLOAD: u64 = 1024 * 16;
+
+fn extract_payload<T: DeserializeOwned + Send>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(MAX_JSON_BODY_PAYLOAD).and(warp::body::json())
+}
+
+/// Lets makers submit signed Moo orders, list them, in total or by maker, and
+/// cancel them by uid with an `OrderCancellation` they signed. Only makers
+/// `contract` whitelists can submit orders.
+pub fn get_maker_orders(
+    order_book: Arc<OrderBook>,
+    contract: MooSettlementContract,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    submit(order_book.clone(), contract)
+        .or(list(order_book.clone()))
+        .or(cancel(order_book))
+}
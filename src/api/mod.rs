@@ -1,18 +1,39 @@
+mod maker_orders;
+mod notify;
 mod solve;
+use crate::order_book::OrderBook;
+use crate::solve::fill_ledger::FillLedger;
+use crate::solve::strategy::SolverStrategy;
+use crate::solve::Config;
+use crate::solve::MOO_SETTLEMENT_CONTRACT_ADDRESS;
+use contracts::MooSettlementContract;
 use std::convert::Infallible;
+use std::sync::Arc;
 use warp::{hyper::StatusCode, Filter, Rejection, Reply};
 use web3::transports::Http;
 use web3::Web3;
 
 pub fn handle_all_routes(
     web3: Web3<Http>,
+    order_book: Arc<OrderBook>,
+    config: Config,
+    strategies: Arc<Vec<Box<dyn SolverStrategy>>>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    let solve = solve::get_solve(web3);
+    let fill_ledger = Arc::new(FillLedger::default());
+    let contract =
+        MooSettlementContract::at(&web3, MOO_SETTLEMENT_CONTRACT_ADDRESS.parse().unwrap());
+    let maker_orders = maker_orders::get_maker_orders(order_book.clone(), contract);
+    let solve = solve::get_solve(web3, order_book, fill_ledger.clone(), config, strategies);
+    let notify = notify::get_notify(fill_ledger);
     let cors = warp::cors()
         .allow_any_origin()
         .allow_methods(vec!["GET", "POST", "DELETE", "OPTIONS", "PUT", "PATCH"])
         .allow_headers(vec!["Origin", "Content-Type", "X-Auth-Token", "X-AppId"]);
-    solve.recover(handle_rejection).with(cors)
+    solve
+        .or(notify)
+        .or(maker_orders)
+        .recover(handle_rejection)
+        .with(cors)
 }
 
 // We turn Rejection into Reply to workaround warp not setting CORS headers on rejections.
@@ -0,0 +1,32 @@
+use crate::models::batch_auction_model::AuctionResult;
+use crate::solve::fill_ledger::FillLedger;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::{hyper::StatusCode, reply, Filter, Rejection, Reply};
+
+#[derive(Deserialize)]
+struct NotifyQuery {
+    auction_id: u64,
+}
+
+fn get_notify_request(
+) -> impl Filter<Extract = (NotifyQuery, AuctionResult), Error = Rejection> + Clone {
+    warp::path!("notify")
+        .and(warp::post())
+        .and(warp::query::<NotifyQuery>())
+        .and(warp::body::json())
+}
+
+pub fn get_notify(
+    fill_ledger: Arc<FillLedger>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    get_notify_request().and_then(move |query: NotifyQuery, result: AuctionResult| {
+        let fill_ledger = fill_ledger.clone();
+        async move {
+            tracing::info!(auction_id = query.auction_id, ?result, "auction result");
+            fill_ledger.notify(query.auction_id, &result);
+            Result::<_, Infallible>::Ok(reply::with_status(reply(), StatusCode::OK))
+        }
+    })
+}
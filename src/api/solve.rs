@@ -1,5 +1,8 @@
-use crate::models::batch_auction_model::{BatchAuctionModel, SettledBatchAuctionModel};
+use crate::models::batch_auction_model::BatchAuctionModel;
+use crate::order_book::OrderBook;
 use crate::solve;
+use crate::solve::fill_ledger::FillLedger;
+use crate::solve::strategy::SolverStrategy;
 use anyhow::Result;
 use hex::{FromHex, FromHexError};
 use primitive_types::H160;
@@ -8,6 +11,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::convert::Infallible;
 use std::str::FromStr;
+use std::sync::Arc;
 use warp::{
     hyper::StatusCode,
     reply::{self, json, with_status, Json, WithStatus},
@@ -28,10 +32,20 @@ impl FromStr for H160Wrapper {
         Ok(H160Wrapper(H160(FromHex::from_hex(s)?)))
     }
 }
-pub fn get_solve_request() -> impl Filter<Extract = (BatchAuctionModel,), Error = Rejection> + Clone
-{
+
+#[derive(Deserialize)]
+pub struct SolveQuery {
+    /// Respond with every distinct solution and its objective value, best
+    /// first, instead of only the best one.
+    #[serde(default)]
+    ranked: bool,
+}
+
+pub fn get_solve_request(
+) -> impl Filter<Extract = (SolveQuery, BatchAuctionModel), Error = Rejection> + Clone {
     warp::path!("solve")
         .and(warp::post())
+        .and(warp::query::<SolveQuery>())
         .and(extract_payload())
 }
 const MAX_JSON_BODY_PAYLOAD: u64 = 1024 * 16 * 100000;
@@ -41,7 +55,7 @@ fn extract_payload<T: DeserializeOwned + Send>(
     // (rejecting huge payloads)...
     warp::body::content_length_limit(MAX_JSON_BODY_PAYLOAD).and(warp::body::json())
 }
-pub fn get_solve_response(result: Result<SettledBatchAuctionModel>) -> WithStatus<Json> {
+pub fn get_solve_response<T: Serialize>(result: Result<T>) -> WithStatus<Json> {
     match result {
         Ok(solve) => reply::with_status(reply::json(&solve), StatusCode::OK),
         Err(err) => convert_get_solve_error_to_reply(err),
@@ -50,9 +64,9 @@ pub fn get_solve_response(result: Result<SettledBatchAuctionModel>) -> WithStatu
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-struct Error<'a> {
-    error_type: &'a str,
-    description: &'a str,
+pub(super) struct Error<'a> {
+    pub(super) error_type: &'a str,
+    pub(super) description: &'a str,
 }
 
 pub fn internal_error(err: anyhow::Error) -> Json {
@@ -69,12 +83,36 @@ pub fn convert_get_solve_error_to_reply(err: anyhow::Error) -> WithStatus<Json>
 
 pub fn get_solve(
     web3: Web3<Http>,
+    order_book: Arc<OrderBook>,
+    fill_ledger: Arc<FillLedger>,
+    config: solve::Config,
+    strategies: Arc<Vec<Box<dyn SolverStrategy>>>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    get_solve_request().and_then(move |model| {
+    get_solve_request().and_then(move |query: SolveQuery, model| {
         let web3 = web3.clone();
+        let order_book = order_book.clone();
+        let fill_ledger = fill_ledger.clone();
+        let strategies = strategies.clone();
         async move {
-            let result = solve::solve(model, web3).await;
-            Result::<_, Infallible>::Ok(get_solve_response(result))
+            let response = if query.ranked {
+                get_solve_response(
+                    solve::solve_ranked(
+                        model,
+                        web3,
+                        &order_book,
+                        &fill_ledger,
+                        &config,
+                        &strategies,
+                    )
+                    .await,
+                )
+            } else {
+                get_solve_response(
+                    solve::solve(model, web3, &order_book, &fill_ledger, &config, &strategies)
+                        .await,
+                )
+            };
+            Result::<_, Infallible>::Ok(response)
         }
     })
 }
@@ -2,7 +2,6 @@
 
 use {
     serde::{de::Error, Deserialize, Deserializer, Serializer},
-    serde_with::{DeserializeAs, SerializeAs},
     std::borrow::Cow,
 };
 
@@ -30,26 +29,3 @@ where
         .ok_or_else(|| D::Error::custom("missing '0x' prefix"))?;
     hex::decode(hex_str).map_err(D::Error::custom)
 }
-
-pub struct BytesHex(());
-
-impl<T> SerializeAs<T> for BytesHex
-where
-    T: AsRef<[u8]>,
-{
-    fn serialize_as<S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serialize(bytes, serializer)
-    }
-}
-
-impl<'de> DeserializeAs<'de, Vec<u8>> for BytesHex {
-    fn deserialize_as<D>(deserializer: D) -> Result<Vec<u8>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserialize(deserializer)
-    }
-}
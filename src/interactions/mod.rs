@@ -1,11 +1,11 @@
 pub(crate) mod bytes_hex;
 pub mod settlement_contract;
 pub(crate) mod u256_decimal;
+pub mod uniswap_v2;
+pub mod weth;
 
 use {
     ethcontract::Bytes,
-    serde::{Deserialize, Serialize},
-    std::fmt::{self, Debug, Formatter},
     web3::types::{H160, U256},
 };
 
@@ -41,36 +41,3 @@ impl Interaction for EncodedInteraction {
         vec![self.clone()]
     }
 }
-
-impl Interaction for InteractionData {
-    fn encode(&self) -> Vec<EncodedInteraction> {
-        vec![EncodedInteraction {
-            target: self.target,
-            value: self.value,
-            call_data: Bytes(self.call_data.clone()),
-        }]
-    }
-}
-
-#[derive(Eq, PartialEq, Clone, Hash, Default, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct InteractionData {
-    pub target: H160,
-    #[serde(with = "u256_decimal")]
-    pub value: U256,
-    #[serde(with = "bytes_hex")]
-    pub call_data: Vec<u8>,
-}
-
-impl Debug for InteractionData {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        f.debug_struct("InteractionData")
-            .field("target", &self.target)
-            .field("value", &self.value)
-            .field(
-                "call_data",
-                &format_args!("0x{}", hex::encode(&self.call_data)),
-            )
-            .finish()
-    }
-}
@@ -16,15 +16,7 @@ pub struct MooSettlementInteraction {
 impl Interaction for MooSettlementInteraction {
     fn encode(&self) -> Vec<EncodedInteraction> {
         let method = self.moo.swap(
-            (
-                self.order.token_in,
-                self.order.amount_in,
-                self.order.token_out,
-                self.order.amount_out,
-                self.order.valid_to,
-                self.order.maker,
-                self.order.uid.clone(),
-            ),
+            self.order.as_tuple(),
             ethcontract::Bytes(self.signature.clone().0),
         );
         let call_data = method.tx.data.expect("no call data").0;
@@ -0,0 +1,41 @@
+use crate::interactions::{EncodedInteraction, Interaction};
+use {
+    contracts::{IUniswapLikePair, ERC20},
+    web3::types::{H160, U256},
+};
+
+/// Swap directly on a Uniswap V2 like pair: the input is transferred to the
+/// pair first, which then pays out the output to `receiver`.
+#[derive(Clone, Debug)]
+pub struct UniswapV2Interaction {
+    pub pair: IUniswapLikePair,
+    pub token_in: ERC20,
+    pub amount_in: U256,
+    /// Amounts of the pair's `token0` and `token1` to pay out.
+    pub amounts_out: (U256, U256),
+    pub receiver: H160,
+}
+
+impl Interaction for UniswapV2Interaction {
+    fn encode(&self) -> Vec<EncodedInteraction> {
+        let transfer = self.token_in.transfer(self.pair.address(), self.amount_in);
+        let swap = self.pair.swap(
+            self.amounts_out.0,
+            self.amounts_out.1,
+            self.receiver,
+            ethcontract::Bytes(Vec::new()),
+        );
+        vec![
+            EncodedInteraction {
+                target: self.token_in.address(),
+                value: 0.into(),
+                call_data: ethcontract::Bytes(transfer.tx.data.expect("no call data").0),
+            },
+            EncodedInteraction {
+                target: self.pair.address(),
+                value: 0.into(),
+                call_data: ethcontract::Bytes(swap.tx.data.expect("no call data").0),
+            },
+        ]
+    }
+}
@@ -0,0 +1,21 @@
+use crate::interactions::{EncodedInteraction, Interaction};
+use {contracts::WETH9, web3::types::U256};
+
+/// Unwraps `amount` WETH held by the settlement contract into native ETH,
+/// which needs no approval.
+#[derive(Clone, Debug)]
+pub struct WethInteraction {
+    pub weth: WETH9,
+    pub amount: U256,
+}
+
+impl Interaction for WethInteraction {
+    fn encode(&self) -> Vec<EncodedInteraction> {
+        let method = self.weth.withdraw(self.amount).tx;
+        vec![EncodedInteraction {
+            target: self.weth.address(),
+            value: U256::zero(),
+            call_data: ethcontract::Bytes(method.data.expect("no call data").0),
+        }]
+    }
+}
@@ -1,16 +1,27 @@
 pub mod api;
 mod interactions;
 pub mod models;
+pub mod order_book;
 pub mod solve;
 pub mod tracing_helper;
 
+use order_book::OrderBook;
+use solve::strategy::SolverStrategy;
+use solve::Config;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::{task, task::JoinHandle};
 use web3::transports::Http;
 use web3::Web3;
 
-pub fn serve_task(address: SocketAddr, web3: Web3<Http>) -> JoinHandle<()> {
-    let filter = api::handle_all_routes(web3);
+pub fn serve_task(
+    address: SocketAddr,
+    web3: Web3<Http>,
+    order_book: Arc<OrderBook>,
+    config: Config,
+    strategies: Arc<Vec<Box<dyn SolverStrategy>>>,
+) -> JoinHandle<()> {
+    let filter = api::handle_all_routes(web3, order_book, config, strategies);
     tracing::info!(%address, "serving api");
     task::spawn(warp::serve(filter).bind(address))
 }
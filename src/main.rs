@@ -1,9 +1,22 @@
 #![recursion_limit = "256"]
+use contracts::ethcontract::{Password, PrivateKey};
+use contracts::MooSettlementContract;
+use moo_solver::order_book::maker::{self, OrderParameters, SigningKey};
+use moo_solver::order_book::signature::DomainSeparator;
+use moo_solver::order_book::{OrderBook, OrderUid};
 use moo_solver::serve_task;
+use moo_solver::solve::risk::RiskLimits;
+use moo_solver::solve::slippage::{parse_relative_bps, SlippagePolicy};
+use moo_solver::solve::strategy;
+use moo_solver::solve::{weth_address, Config, MOO_SETTLEMENT_CONTRACT_ADDRESS};
 use moo_solver::tracing_helper::initialize;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 use web3::transports::Http;
+use web3::types::{H160, U256};
 use web3::Web3;
 
 #[derive(Debug, StructOpt)]
@@ -13,29 +26,249 @@ struct Arguments {
     #[structopt(long, env = "BIND_ADDRESS", default_value = "0.0.0.0:8000")]
     bind_address: SocketAddr,
 
-    /// The relative slippage tolerance to apply to on-chain swaps.
-    #[structopt(long, env, default_value = "10")]
+    /// The relative slippage tolerance to apply to on-chain swaps, at most
+    /// 10000 bps.
+    #[structopt(long, env, default_value = "10", parse(try_from_str = parse_relative_bps))]
     relative_slippage_bps: u32,
 
     /// The absolute slippage tolerance in native token units to cap relative
     /// slippage at. Default is 0.007 ETH.
+    #[structopt(long, env, default_value = "0.007")]
+    absolute_slippage_in_native_token: f64,
+
+    /// The most the maker orders of a single maker may be worth in native
+    /// token units in a solution. Unlimited by default.
+    #[structopt(long, env)]
+    maker_risk_limit_in_native_token: Option<f64>,
+
+    /// The most the maker orders trading a token may be worth in native token
+    /// units in a solution. Unlimited by default.
+    #[structopt(long, env)]
+    token_risk_limit_in_native_token: Option<f64>,
+
+    /// The most all maker orders of a solution may be worth in native token
+    /// units. Unlimited by default.
+    #[structopt(long, env)]
+    auction_risk_limit_in_native_token: Option<f64>,
+
+    /// The strategies to build candidate solutions with, separated by commas.
+    /// Names joined by `+` combine into a single strategy, e.g. `cow+moo+amm`.
+    #[structopt(long, env, default_value = "naive")]
+    strategies: String,
+
+    /// The chain `MooSettlementContract` is deployed on, which maker orders
+    /// are signed for. Default is Goerli.
+    #[structopt(long, env, default_value = "5")]
+    chain_id: u64,
+
+    /// The `WETH9` contract native ETH gets wrapped into and unwrapped from.
+    /// Defaults to the canonical one of the chain.
+    #[structopt(long, env)]
+    weth_address: Option<H160>,
+
+    /// Path to a JSON file with the signed Moo maker orders to match user
+    /// orders against.
     #[structopt(long, env)]
-    absolute_slippage_in_native_token: Option<f64>,
+    maker_orders_path: Option<PathBuf>,
+
+    /// How many seconds past the auction deadline maker orders have to stay
+    /// valid to be used, so that settlements don't revert for expiry.
+    #[structopt(long, env, default_value = "60")]
+    maker_order_validity_margin_secs: u64,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Tools for Moo makers.
+    Maker(MakerCommand),
+}
+
+#[derive(Debug, StructOpt)]
+enum MakerCommand {
+    /// Signs a maker order and prints it as JSON for the maker orders API.
+    Sign(SignArguments),
+    /// Signs the cancellation of a maker order and prints it as JSON for the
+    /// maker orders API.
+    Cancel(CancelArguments),
+}
+
+#[derive(Debug, StructOpt)]
+struct SignArguments {
+    /// The token the maker takes in.
+    #[structopt(long)]
+    token_in: H160,
+    /// How much of `token_in` the maker takes in, in atoms.
+    #[structopt(long, parse(try_from_str = U256::from_dec_str))]
+    amount_in: U256,
+    /// The token the maker gives out.
+    #[structopt(long)]
+    token_out: H160,
+    /// How much of `token_out` the maker gives out, in atoms.
+    #[structopt(long, parse(try_from_str = U256::from_dec_str))]
+    amount_out: U256,
+    /// The unix timestamp the order is valid until.
+    #[structopt(long)]
+    valid_to: u32,
+
+    #[structopt(flatten)]
+    signer: SignerArguments,
+}
+
+#[derive(Debug, StructOpt)]
+struct CancelArguments {
+    /// The uid of the order to cancel.
+    #[structopt(long)]
+    uid: OrderUid,
+
+    #[structopt(flatten)]
+    signer: SignerArguments,
+}
+
+#[derive(Debug, StructOpt)]
+struct SignerArguments {
+    /// The chain to sign for. Default is Goerli.
+    #[structopt(long, default_value = "5")]
+    chain_id: u64,
+    /// The `MooSettlementContract` to sign for.
+    #[structopt(long, default_value = MOO_SETTLEMENT_CONTRACT_ADDRESS)]
+    settlement_contract: H160,
+    /// A node of the chain to check that the settlement contract is deployed
+    /// with the domain signed for. Signing needs no node.
+    #[structopt(long, env = "NODE_URL")]
+    node_url: Option<String>,
+
+    /// The hex encoded private key of the maker.
+    #[structopt(
+        long,
+        env = "MAKER_PRIVATE_KEY",
+        hide_env_values = true,
+        required_unless = "keystore",
+        conflicts_with = "keystore"
+    )]
+    private_key: Option<PrivateKey>,
+    /// An encrypted JSON keystore file with the key of the maker instead.
+    #[structopt(long, requires = "keystore-password")]
+    keystore: Option<PathBuf>,
+    /// The password to decrypt `keystore` with.
+    #[structopt(long, env = "KEYSTORE_PASSWORD", hide_env_values = true)]
+    keystore_password: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
     let args = Arguments::from_args();
+    match args.command {
+        Some(Command::Maker(MakerCommand::Sign(args))) => return sign_maker_order(args).await,
+        Some(Command::Maker(MakerCommand::Cancel(args))) => return cancel_maker_order(args).await,
+        None => (),
+    }
     initialize(args.log_filter.as_str());
     tracing::info!("running data-server with {:#?}", args);
 
     let web3 = create_web3();
-    let serve_task = serve_task(args.bind_address, web3);
+    let contract =
+        MooSettlementContract::at(&web3, MOO_SETTLEMENT_CONTRACT_ADDRESS.parse().unwrap());
+    let domain = DomainSeparator::new(args.chain_id, contract.address());
+    if let Err(err) = domain.check_deployment(&contract).await {
+        tracing::error!(?err, "maker orders won't verify on-chain");
+    }
+    let order_book = match &args.maker_orders_path {
+        Some(path) => OrderBook::from_file(path, domain, &contract)
+            .await
+            .expect("failed to load maker orders"),
+        None => OrderBook::new(domain),
+    }
+    .with_validity_margin(Duration::from_secs(args.maker_order_validity_margin_secs));
+    let config = Config {
+        slippage: SlippagePolicy {
+            relative_bps: args.relative_slippage_bps,
+            absolute_in_native_token: Some(args.absolute_slippage_in_native_token),
+        },
+        risk_limits: RiskLimits {
+            per_maker: args.maker_risk_limit_in_native_token,
+            per_token: args.token_risk_limit_in_native_token,
+            per_auction: args.auction_risk_limit_in_native_token,
+        },
+        weth: args
+            .weth_address
+            .or_else(|| weth_address(args.chain_id))
+            .expect("no known WETH address for the chain, set --weth-address"),
+    };
+    let strategies = strategy::from_names(&args.strategies).expect("invalid strategies");
+    let serve_task = serve_task(
+        args.bind_address,
+        web3,
+        Arc::new(order_book),
+        config,
+        Arc::new(strategies),
+    );
     tokio::select! {
         result = serve_task => tracing::error!(?result, "serve task exited"),
     };
 }
 
+async fn sign_maker_order(args: SignArguments) {
+    let parameters = OrderParameters {
+        token_in: args.token_in,
+        amount_in: args.amount_in,
+        token_out: args.token_out,
+        amount_out: args.amount_out,
+        valid_to: args.valid_to,
+    };
+    let signer = args.signer;
+    signer.check_deployment().await;
+    let signed = maker::sign_order(
+        signer.chain_id,
+        signer.settlement_contract,
+        &parameters,
+        &signer.key(),
+    )
+    .expect("failed to sign maker order");
+    println!("{}", serde_json::to_string_pretty(&signed).unwrap());
+}
+
+async fn cancel_maker_order(args: CancelArguments) {
+    let signer = args.signer;
+    signer.check_deployment().await;
+    let cancellation = maker::sign_cancellation(
+        signer.chain_id,
+        signer.settlement_contract,
+        &args.uid,
+        &signer.key(),
+    )
+    .expect("failed to sign maker order cancellation");
+    println!("{}", serde_json::to_string_pretty(&cancellation).unwrap());
+}
+
+impl SignerArguments {
+    /// Checks the domain against the deployment if there is a node to ask.
+    async fn check_deployment(&self) {
+        let Some(node_url) = &self.node_url else {
+            return;
+        };
+        let web3 = Web3::new(Http::new(node_url).expect("invalid node url"));
+        let contract = MooSettlementContract::at(&web3, self.settlement_contract);
+        DomainSeparator::new(self.chain_id, self.settlement_contract)
+            .check_deployment(&contract)
+            .await
+            .expect("failed to check the EIP-712 domain of MooSettlementContract");
+    }
+
+    fn key(self) -> SigningKey {
+        match (self.private_key, self.keystore, self.keystore_password) {
+            (Some(key), _, _) => SigningKey::PrivateKey(key),
+            (None, Some(path), Some(password)) => SigningKey::Keystore {
+                path,
+                password: Password::new(password),
+            },
+            _ => unreachable!("structopt requires a private key or keystore"),
+        }
+    }
+}
+
 fn create_web3() -> Web3<Http> {
     let infura_key = std::env::var("INFURA_KEY").expect("Set INFURA_KEY env variable");
     let http = Http::new(format!("https://goerli.infura.io/v3/{infura_key}").as_str()).unwrap();
@@ -5,13 +5,15 @@ use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::collections::{BTreeMap, HashMap};
-use web3::types::H160;
 use web3::types::U256;
+use web3::types::{H160, H256};
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct BatchAuctionModel {
     pub tokens: BTreeMap<H160, TokenInfoModel>,
     pub orders: BTreeMap<usize, OrderModel>,
+    #[serde(default)]
+    pub amms: BTreeMap<usize, AmmModel>,
     pub metadata: Option<MetadataModel>,
     pub instance_name: Option<String>,
     pub time_limit: Option<u64>,
@@ -44,6 +46,35 @@ pub struct TokenInfoModel {
     pub internal_buffer: Option<U256>,
 }
 
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AmmModel {
+    #[serde(flatten)]
+    pub parameters: AmmParameters,
+    /// Fraction of the input amount the pool keeps, e.g. `"0.003"`.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub fee: f64,
+    pub cost: CostModel,
+    pub mandatory: bool,
+    pub address: H160,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum AmmParameters {
+    ConstantProduct(ConstantProductPoolParameters),
+    /// Pool kinds we can't route through yet.
+    #[serde(other)]
+    Unsupported,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConstantProductPoolParameters {
+    #[serde_as(as = "BTreeMap<_, DecimalU256>")]
+    pub reserves: BTreeMap<H160, U256>,
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct CostModel {
     #[serde(with = "u256_decimal")]
@@ -134,9 +165,18 @@ pub struct SettledBatchAuctionModel {
     pub interaction_data: Vec<InteractionData>,
 }
 
+/// A solution along with the objective value it was ranked by.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RankedSolutionModel {
+    pub objective_value: f64,
+    pub solution: SettledBatchAuctionModel,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MetadataModel {
     pub environment: Option<String>,
+    /// The gas price in wei the settlement is expected to pay.
+    pub gas_price: Option<f64>,
 }
 
 #[serde_as]
@@ -166,3 +206,18 @@ pub struct ExecutedAmmModel {
     pub exec_buy_amount: U256,
     pub exec_plan: Option<ExecutionPlanCoordinatesModel>,
 }
+
+/// What the driver reports back about an auction the solver proposed a
+/// solution for.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuctionResult {
+    /// Our solution did not win the auction.
+    Rejected,
+    /// Our solution won and is being submitted.
+    Won,
+    /// Our solution was mined in the given transaction.
+    Settled(H256),
+    /// Our solution won but its submission failed or reverted.
+    Failed,
+}
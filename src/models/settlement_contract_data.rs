@@ -1,15 +1,187 @@
-use contracts::ethcontract::Bytes;
-use serde::Serialize;
-use web3::types::{H160, U256};
+use crate::interactions::u256_decimal;
+use crate::order_book::signature::{self, DomainSeparator};
+use anyhow::{anyhow, ensure, Result};
+use contracts::ethcontract;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use web3::types::{Bytes, H160, H256, U256};
 
-#[derive(Clone, Debug, Default, Serialize)]
+/// `MooSettlementContract` order as the generated bindings take it.
+pub(crate) type OrderTuple = (
+    H160,
+    U256,
+    H160,
+    U256,
+    U256,
+    H160,
+    ethcontract::Bytes<Vec<u8>>,
+);
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Order {
     pub(crate) token_in: H160,
+    #[serde(with = "u256_decimal")]
     pub(crate) amount_in: U256,
     pub(crate) token_out: H160,
+    #[serde(with = "u256_decimal")]
     pub(crate) amount_out: U256,
+    #[serde(with = "u256_decimal")]
     pub(crate) valid_to: U256,
     pub(crate) maker: H160,
-    pub(crate) uid: Bytes<Vec<u8>>,
+    pub(crate) uid: Bytes,
+}
+
+impl Order {
+    /// The uid `order` has to carry: the EIP-712 hash of the order with an
+    /// empty uid, followed by its maker and `valid_to`. Fails for a
+    /// `valid_to` that doesn't fit into 32 bits.
+    pub fn compute_uid(&self, domain: &DomainSeparator) -> Result<OrderUid> {
+        let valid_to = u32::try_from(self.valid_to)
+            .map_err(|_| anyhow!("maker order valid_to {} is too large", self.valid_to))?;
+        let hash = signature::order_hash(
+            domain,
+            &Order {
+                uid: Bytes::default(),
+                ..self.clone()
+            },
+        );
+        Ok(OrderUid::from_parts(hash, self.maker, valid_to))
+    }
+
+    /// The uid of the order, as long as it is well-formed and belongs to its
+    /// maker and `valid_to`. Only `compute_uid` can tell whether it belongs
+    /// to the rest of the order as well.
+    pub fn parse_uid(&self) -> Result<OrderUid> {
+        let uid = OrderUid(
+            self.uid
+                .0
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!("maker order uid is not {UID_LEN} bytes long"))?,
+        );
+        let (_, maker, valid_to) = uid.parts();
+        ensure!(
+            maker == self.maker && U256::from(valid_to) == self.valid_to,
+            "maker order uid belongs to another maker or valid_to"
+        );
+        Ok(uid)
+    }
+
+    pub(crate) fn as_tuple(&self) -> OrderTuple {
+        (
+            self.token_in,
+            self.amount_in,
+            self.token_out,
+            self.amount_out,
+            self.valid_to,
+            self.maker,
+            ethcontract::Bytes(self.uid.0.clone()),
+        )
+    }
+}
+
+const UID_LEN: usize = 56;
+
+/// Identifies a maker order like CoW Protocol identifies orders, by the hash
+/// of the order, its maker and its `valid_to`, so that orders can't collide
+/// and tell when they expire.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OrderUid(pub [u8; UID_LEN]);
+
+impl OrderUid {
+    fn from_parts(hash: H256, maker: H160, valid_to: u32) -> Self {
+        let mut uid = [0u8; UID_LEN];
+        uid[..32].copy_from_slice(hash.as_bytes());
+        uid[32..52].copy_from_slice(maker.as_bytes());
+        uid[52..].copy_from_slice(&valid_to.to_be_bytes());
+        Self(uid)
+    }
+
+    /// The order hash, maker and `valid_to` the uid is made of.
+    pub fn parts(&self) -> (H256, H160, u32) {
+        (
+            H256::from_slice(&self.0[..32]),
+            H160::from_slice(&self.0[32..52]),
+            u32::from_be_bytes(self.0[52..].try_into().unwrap()),
+        )
+    }
+
+    pub fn valid_to(&self) -> u32 {
+        self.parts().2
+    }
+}
+
+impl fmt::Display for OrderUid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for OrderUid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for OrderUid {
+    type Err = hex::FromHexError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        let mut uid = [0u8; UID_LEN];
+        hex::decode_to_slice(s, &mut uid)?;
+        Ok(Self(uid))
+    }
+}
+
+/// A maker order together with the signature `MooSettlementContract::swap`
+/// verifies it against.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignedOrder {
+    #[serde(flatten)]
+    pub(crate) order: Order,
+    pub(crate) signature: Bytes,
+}
+
+/// The signature of the maker over the `OrderCancellation` of one of its
+/// orders, which the maker orders API takes to cancel it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OrderCancellation {
+    pub signature: Bytes,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_uids_from_the_order() {
+        let domain = DomainSeparator(H256::repeat_byte(0xdd));
+        let mut order = Order {
+            token_in: H160::repeat_byte(1),
+            amount_in: 100.into(),
+            token_out: H160::repeat_byte(2),
+            amount_out: 200.into(),
+            valid_to: 1_700_000_000.into(),
+            maker: H160::repeat_byte(3),
+            uid: Bytes::default(),
+        };
+        let uid = order.compute_uid(&domain).unwrap();
+        assert_eq!(uid.valid_to(), 1_700_000_000);
+        assert_eq!(uid.parts().1, H160::repeat_byte(3));
+        // The uid itself doesn't go into it.
+        order.uid = Bytes(uid.0.to_vec());
+        assert_eq!(order.compute_uid(&domain).unwrap(), uid);
+        assert_eq!(order.parse_uid().unwrap(), uid);
+        assert_eq!(uid.to_string().parse::<OrderUid>().unwrap(), uid);
+
+        let mut other = order.clone();
+        other.amount_out = 201.into();
+        assert_ne!(other.compute_uid(&domain).unwrap(), uid);
+        other.maker = H160::repeat_byte(4);
+        assert!(other.parse_uid().is_err());
+        other.valid_to = U256::from(u32::MAX) + 1;
+        assert!(other.compute_uid(&domain).is_err());
+    }
 }
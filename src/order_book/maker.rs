@@ -0,0 +1,137 @@
+//! What makers need to produce orders the order book takes.
+
+use super::signature::{self, DomainSeparator};
+use crate::models::settlement_contract_data::{Order, OrderCancellation, OrderUid, SignedOrder};
+use anyhow::{Context, Result};
+use contracts::ethcontract::{Password, PrivateKey};
+use std::path::PathBuf;
+use web3::types::{Bytes, H160, U256};
+
+/// What a maker order trades and until when. Its maker and uid follow from
+/// the key it is signed with and the order itself.
+#[derive(Clone, Debug)]
+pub struct OrderParameters {
+    pub token_in: H160,
+    pub amount_in: U256,
+    pub token_out: H160,
+    pub amount_out: U256,
+    pub valid_to: u32,
+}
+
+/// Where the key to sign maker orders with comes from.
+#[derive(Debug)]
+pub enum SigningKey {
+    PrivateKey(PrivateKey),
+    /// An encrypted JSON keystore file, like the ones geth writes.
+    Keystore {
+        path: PathBuf,
+        password: Password,
+    },
+}
+
+impl SigningKey {
+    fn private_key(&self) -> Result<PrivateKey> {
+        match self {
+            SigningKey::PrivateKey(key) => Ok(key.clone()),
+            SigningKey::Keystore { path, password } => {
+                let raw = eth_keystore::decrypt_key(path, password.as_bytes())
+                    .with_context(|| format!("failed to decrypt keystore {path:?}"))?;
+                Ok(PrivateKey::from_slice(raw)?)
+            }
+        }
+    }
+}
+
+/// Builds the maker order, derives its uid and signs it for
+/// `MooSettlementContract` at `settlement_contract` on `chain_id`, ready to be
+/// submitted to the maker orders API.
+pub fn sign_order(
+    chain_id: u64,
+    settlement_contract: H160,
+    parameters: &OrderParameters,
+    key: &SigningKey,
+) -> Result<SignedOrder> {
+    let key = key.private_key()?;
+    let domain = DomainSeparator::new(chain_id, settlement_contract);
+    let mut order = Order {
+        token_in: parameters.token_in,
+        amount_in: parameters.amount_in,
+        token_out: parameters.token_out,
+        amount_out: parameters.amount_out,
+        valid_to: parameters.valid_to.into(),
+        maker: key.public_address(),
+        uid: Bytes::default(),
+    };
+    order.uid = Bytes(order.compute_uid(&domain)?.0.to_vec());
+    let signature = signature::sign(&domain, &order, &*key)?;
+    Ok(SignedOrder { order, signature })
+}
+
+/// Signs the cancellation of the maker order with `uid` for the same
+/// deployment, ready to be sent along with its `DELETE` to the maker orders
+/// API.
+pub fn sign_cancellation(
+    chain_id: u64,
+    settlement_contract: H160,
+    uid: &OrderUid,
+    key: &SigningKey,
+) -> Result<OrderCancellation> {
+    let key = key.private_key()?;
+    let domain = DomainSeparator::new(chain_id, settlement_contract);
+    let signature = signature::sign_cancellation(&domain, uid, &*key)?;
+    Ok(OrderCancellation { signature })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_book::OrderBook;
+
+    #[test]
+    fn signs_orders_the_order_book_takes() {
+        let settlement_contract = H160::repeat_byte(0xee);
+        let parameters = OrderParameters {
+            token_in: H160::repeat_byte(1),
+            amount_in: 100.into(),
+            token_out: H160::repeat_byte(2),
+            amount_out: 200.into(),
+            valid_to: u32::MAX,
+        };
+        let key = SigningKey::PrivateKey(PrivateKey::from_raw([0x11; 32]).unwrap());
+        let signed = sign_order(5, settlement_contract, &parameters, &key).unwrap();
+
+        let order_book = OrderBook::new(DomainSeparator::new(5, settlement_contract));
+        order_book.insert(signed.clone()).unwrap();
+        let other_chain = OrderBook::new(DomainSeparator::new(1, settlement_contract));
+        assert!(other_chain.insert(signed.clone()).is_err());
+
+        let uid = signed.order.parse_uid().unwrap();
+        let cancellation = sign_cancellation(5, settlement_contract, &uid, &key).unwrap();
+        assert!(order_book
+            .cancel(&uid, &cancellation.signature.0)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn decrypts_keystores() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw = [0x11; 32];
+        eth_keystore::encrypt_key(
+            dir.path(),
+            &mut rand::thread_rng(),
+            raw,
+            "password",
+            Some("maker"),
+        )
+        .unwrap();
+        let key = SigningKey::Keystore {
+            path: dir.path().join("maker"),
+            password: Password::new("password"),
+        };
+        assert_eq!(
+            key.private_key().unwrap().public_address(),
+            PrivateKey::from_raw(raw).unwrap().public_address()
+        );
+    }
+}
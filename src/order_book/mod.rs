@@ -0,0 +1,360 @@
+pub mod maker;
+pub mod signature;
+
+pub use crate::models::settlement_contract_data::OrderUid;
+
+use crate::models::settlement_contract_data::SignedOrder;
+use anyhow::{ensure, Context, Result};
+use contracts::MooSettlementContract;
+use signature::DomainSeparator;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use web3::types::H160;
+
+/// Signed Moo maker orders the solver can settle user orders against, indexed
+/// by the pair of tokens they trade. Makers submit and cancel them while the
+/// solver is running.
+#[derive(Debug)]
+pub struct OrderBook {
+    /// The domain of the settlement contract orders have to be signed for.
+    domain: DomainSeparator,
+    /// How long past the time a settlement is due orders have to stay valid
+    /// for the solver to use them, so that it doesn't revert for expiry.
+    validity_margin: Duration,
+    orders: Mutex<Orders>,
+}
+
+#[derive(Debug, Default)]
+struct Orders {
+    /// The orders keyed by their `token_in` and `token_out`, in the order they
+    /// were submitted.
+    by_pair: HashMap<(H160, H160), Vec<SignedOrder>>,
+    /// The pair of each order, keyed by uid.
+    pairs: HashMap<OrderUid, (H160, H160)>,
+}
+
+impl OrderBook {
+    pub fn new(domain: DomainSeparator) -> Self {
+        Self {
+            domain,
+            validity_margin: Duration::ZERO,
+            orders: Default::default(),
+        }
+    }
+
+    /// Only hands out orders for pairs that stay valid for `margin` past the
+    /// time a settlement is due.
+    pub fn with_validity_margin(self, margin: Duration) -> Self {
+        Self {
+            validity_margin: margin,
+            ..self
+        }
+    }
+
+    /// The domain of the settlement contract orders are signed for.
+    pub fn domain(&self) -> &DomainSeparator {
+        &self.domain
+    }
+
+    /// Loads a JSON array of signed maker orders, checked like submitted ones.
+    /// Orders that have expired since the file was written are skipped.
+    pub async fn from_file(
+        path: &Path,
+        domain: DomainSeparator,
+        contract: &MooSettlementContract,
+    ) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open maker orders file {path:?}"))?;
+        let orders: Vec<SignedOrder> = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("failed to parse maker orders file {path:?}"))?;
+        let order_book = Self::new(domain);
+        let now = now();
+        for order in orders {
+            if order.order.valid_to <= now.into() {
+                tracing::warn!(uid = ?order.order.uid, "skipping expired maker order");
+                continue;
+            }
+            order_book
+                .submit(order, contract)
+                .await
+                .with_context(|| format!("invalid maker order in {path:?}"))?;
+        }
+        Ok(order_book)
+    }
+
+    /// Adds a maker order like `insert`, as long as the settlement contract
+    /// whitelists its maker. `swap` reverts for any other maker.
+    pub async fn submit(
+        &self,
+        signed: SignedOrder,
+        contract: &MooSettlementContract,
+    ) -> Result<()> {
+        let uid = self.validate(&signed)?;
+        let maker = signed.order.maker;
+        let whitelisted = contract
+            .is_whitelisted_maker(maker)
+            .call()
+            .await
+            .context("failed to check the maker whitelist")?;
+        ensure!(whitelisted, "maker {maker:?} is not whitelisted");
+        self.add(uid, signed)
+    }
+
+    /// Adds a maker order, unless it has expired, trades a token for itself,
+    /// either of its amounts is zero, its uid isn't the one derived from it,
+    /// its maker didn't sign it or there already is an order with its uid.
+    pub fn insert(&self, signed: SignedOrder) -> Result<()> {
+        let uid = self.validate(&signed)?;
+        self.add(uid, signed)
+    }
+
+    fn validate(&self, signed: &SignedOrder) -> Result<OrderUid> {
+        let order = &signed.order;
+        ensure!(
+            order.valid_to > now().into(),
+            "maker order expired at {}",
+            order.valid_to
+        );
+        ensure!(
+            order.token_in != order.token_out,
+            "maker order trades a token for itself"
+        );
+        ensure!(
+            !order.amount_in.is_zero() && !order.amount_out.is_zero(),
+            "maker order amounts must not be zero"
+        );
+        let uid = order.parse_uid()?;
+        ensure!(
+            uid == order.compute_uid(&self.domain)?,
+            "maker order uid {uid} is not derived from the order"
+        );
+        signature::verify(&self.domain, signed)?;
+        Ok(uid)
+    }
+
+    fn add(&self, uid: OrderUid, signed: SignedOrder) -> Result<()> {
+        let pair = (signed.order.token_in, signed.order.token_out);
+        let mut orders = self.orders.lock().unwrap();
+        ensure!(
+            !orders.pairs.contains_key(&uid),
+            "maker order {uid} already exists"
+        );
+        orders.pairs.insert(uid, pair);
+        orders.by_pair.entry(pair).or_default().push(signed);
+        Ok(())
+    }
+
+    /// Removes the maker order with `uid` and returns it, `None` if there is
+    /// none. Fails unless its maker signed the cancellation.
+    pub fn cancel(&self, uid: &OrderUid, signature: &[u8]) -> Result<Option<SignedOrder>> {
+        signature::verify_cancellation(&self.domain, uid, signature)?;
+        Ok(self.remove(uid))
+    }
+
+    fn remove(&self, uid: &OrderUid) -> Option<SignedOrder> {
+        let mut orders = self.orders.lock().unwrap();
+        let pair = orders.pairs.remove(uid)?;
+        let pair_orders = orders.by_pair.get_mut(&pair)?;
+        let position = pair_orders
+            .iter()
+            .position(|signed| signed.order.uid.0 == uid.0)?;
+        let cancelled = pair_orders.remove(position);
+        if pair_orders.is_empty() {
+            orders.by_pair.remove(&pair);
+        }
+        Some(cancelled)
+    }
+
+    /// Removes the maker orders that are no longer valid at the unix
+    /// timestamp `now` and returns them.
+    pub fn remove_expired(&self, now: u64) -> Vec<SignedOrder> {
+        let mut orders = self.orders.lock().unwrap();
+        let Orders { by_pair, pairs } = &mut *orders;
+        let mut expired = Vec::new();
+        by_pair.retain(|_, pair_orders| {
+            let (valid, invalid) = std::mem::take(pair_orders)
+                .into_iter()
+                .partition(|signed| signed.order.valid_to > now.into());
+            *pair_orders = valid;
+            expired.extend(invalid);
+            !pair_orders.is_empty()
+        });
+        for signed in &expired {
+            if let Ok(uid) = signed.order.parse_uid() {
+                pairs.remove(&uid);
+            }
+        }
+        expired
+    }
+
+    /// Returns every maker order that hasn't expired yet, or only those of
+    /// `maker`. Expired ones linger until the next `remove_expired`.
+    pub fn orders(&self, maker: Option<H160>) -> Vec<SignedOrder> {
+        let now = now();
+        self.orders
+            .lock()
+            .unwrap()
+            .by_pair
+            .values()
+            .flatten()
+            .filter(|signed| signed.order.valid_to > now.into())
+            .filter(|signed| maker.is_none_or(|maker| signed.order.maker == maker))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the maker orders that take `token_in` and give out `token_out`
+    /// and stay valid for the validity margin past the unix timestamp
+    /// `settled_by`.
+    pub(crate) fn orders_for_pair(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        settled_by: u64,
+    ) -> Vec<SignedOrder> {
+        let valid_until = settled_by.saturating_add(self.validity_margin.as_secs());
+        self.orders
+            .lock()
+            .unwrap()
+            .by_pair
+            .get(&(token_in, token_out))
+            .into_iter()
+            .flatten()
+            .filter(|signed| signed.order.valid_to >= valid_until.into())
+            .cloned()
+            .collect()
+    }
+}
+
+/// The current unix timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solve::fixtures::token;
+    use web3::types::H256;
+
+    fn domain() -> DomainSeparator {
+        DomainSeparator(H256::repeat_byte(0xdd))
+    }
+
+    fn maker_order(nonce: u8, token_in: u8, token_out: u8) -> SignedOrder {
+        signature::test_order(&domain(), nonce, (token(token_in), token(token_out)))
+    }
+
+    fn nonces(orders: Vec<SignedOrder>) -> Vec<u64> {
+        let mut nonces = orders
+            .iter()
+            .map(|signed| signed.order.amount_in.as_u64() - 100)
+            .collect::<Vec<_>>();
+        nonces.sort();
+        nonces
+    }
+
+    #[test]
+    fn indexes_orders_by_pair() {
+        let order_book = OrderBook::new(domain());
+        for order in [
+            maker_order(1, 1, 2),
+            maker_order(2, 2, 1),
+            maker_order(3, 1, 2),
+        ] {
+            order_book.insert(order).unwrap();
+        }
+        assert_eq!(
+            nonces(order_book.orders_for_pair(token(1), token(2), 0)),
+            [1, 3]
+        );
+        assert_eq!(
+            nonces(order_book.orders_for_pair(token(2), token(1), 0)),
+            [2]
+        );
+        assert_eq!(nonces(order_book.orders(None)), [1, 2, 3]);
+        let maker = order_book.orders(None)[0].order.maker;
+        assert_eq!(nonces(order_book.orders(Some(maker))), [1, 2, 3]);
+        assert!(order_book.orders(Some(token(2))).is_empty());
+
+        let uid = maker_order(1, 1, 2).order.parse_uid().unwrap();
+        let cancellation =
+            signature::sign_cancellation(&domain(), &uid, &*signature::test_key()).unwrap();
+        // Only the maker can cancel its orders.
+        let other_uid = maker_order(3, 1, 2).order.parse_uid().unwrap();
+        assert!(order_book.cancel(&other_uid, &cancellation.0).is_err());
+        assert!(order_book.cancel(&uid, &cancellation.0).unwrap().is_some());
+        assert!(order_book.cancel(&uid, &cancellation.0).unwrap().is_none());
+        assert_eq!(
+            nonces(order_book.orders_for_pair(token(1), token(2), 0)),
+            [3]
+        );
+        // The uid is free again.
+        order_book.insert(maker_order(1, 1, 2)).unwrap();
+        assert_eq!(
+            nonces(order_book.orders_for_pair(token(1), token(2), 0)),
+            [1, 3]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_orders() {
+        let order_book = OrderBook::new(domain());
+        order_book.insert(maker_order(1, 1, 2)).unwrap();
+        assert!(order_book.insert(maker_order(1, 1, 2)).is_err());
+        assert!(order_book.insert(maker_order(2, 1, 1)).is_err());
+
+        let mut forged = maker_order(3, 1, 2);
+        forged.order.maker = token(5);
+        assert!(order_book.insert(forged).is_err());
+        let mut not_derived = maker_order(4, 1, 2);
+        not_derived.order.uid = maker_order(5, 1, 2).order.uid;
+        assert!(order_book.insert(not_derived).is_err());
+        let mut unsigned = maker_order(6, 1, 2);
+        unsigned.signature = maker_order(7, 1, 2).signature;
+        assert!(order_book.insert(unsigned).is_err());
+        let now = u32::try_from(now()).unwrap();
+        let expired = signature::test_order_valid_to(&domain(), 8, (token(1), token(2)), now);
+        assert!(order_book.insert(expired).is_err());
+        assert_eq!(nonces(order_book.orders(None)), [1]);
+    }
+
+    #[test]
+    fn sweeps_expired_orders() {
+        let order_book = OrderBook::new(domain()).with_validity_margin(Duration::from_secs(60));
+        let tokens = (token(1), token(2));
+        // Expiry is relative to `now` rather than the clock from here on.
+        let now = now();
+        let order = |nonce, valid_in: u64| {
+            let valid_to = u32::try_from(now + valid_in).unwrap();
+            signature::test_order_valid_to(&domain(), nonce, tokens, valid_to)
+        };
+        for (nonce, valid_in) in [(1, 1_000), (2, 1_100), (3, 1_200)] {
+            order_book.insert(order(nonce, valid_in)).unwrap();
+        }
+        order_book.insert(maker_order(4, 2, 1)).unwrap();
+
+        // Orders have to stay valid for the margin past the settlement.
+        assert_eq!(
+            nonces(order_book.orders_for_pair(token(1), token(2), now + 940)),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            nonces(order_book.orders_for_pair(token(1), token(2), now + 941)),
+            [2, 3]
+        );
+
+        assert_eq!(nonces(order_book.remove_expired(now + 1_100)), [1, 2]);
+        assert!(order_book.remove_expired(now + 1_100).is_empty());
+        assert_eq!(nonces(order_book.orders(None)), [3, 4]);
+        assert_eq!(nonces(order_book.remove_expired(now + 1_200)), [3]);
+        assert_eq!(nonces(order_book.orders(None)), [4]);
+        // The uid of a swept order is free again.
+        order_book.insert(order(1, 1_000)).unwrap();
+    }
+}
@@ -0,0 +1,371 @@
+//! The order hashing and signature recovery of `MooSettlementContract`, so
+//! that maker orders with invalid signatures are rejected before they revert
+//! a settlement.
+//!
+//! Makers sign the `personal_sign` message of the EIP-712 hash of an order:
+//! `recoverSigner(getEthSignedMessageHash(generateEIP712Hash(order)))` has to
+//! be the maker. Cancellations are signed the same way, as an
+//! `OrderCancellation` of the uid for the same domain.
+
+use crate::models::settlement_contract_data::{Order, OrderUid, SignedOrder};
+use anyhow::{anyhow, ensure, Context, Result};
+use contracts::MooSettlementContract;
+use web3::ethabi::{encode, Token};
+use web3::signing::{self, keccak256, Key};
+use web3::types::{Bytes, H160, H256};
+
+const ORDER_TYPE: &str = "Order(address tokenIn,uint256 amountIn,address tokenOut,uint256 amountOut,uint256 validTo,address maker,bytes uid)";
+const CANCELLATION_TYPE: &str = "OrderCancellation(bytes orderUid)";
+
+/// The name and version of the EIP-712 domain of `MooSettlementContract`,
+/// which is the `MooMaker` contract of its ABI.
+const DOMAIN_NAME: &str = "MooMaker";
+const DOMAIN_VERSION: &str = "1";
+
+const DOMAIN_TYPE: &str =
+    "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+
+/// The EIP-712 domain separator of a `MooSettlementContract` deployment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DomainSeparator(pub H256);
+
+impl DomainSeparator {
+    pub fn new(chain_id: u64, settlement_contract: H160) -> Self {
+        Self(H256(keccak256(&encode(&[
+            Token::FixedBytes(keccak256(DOMAIN_TYPE.as_bytes()).to_vec()),
+            Token::FixedBytes(keccak256(DOMAIN_NAME.as_bytes()).to_vec()),
+            Token::FixedBytes(keccak256(DOMAIN_VERSION.as_bytes()).to_vec()),
+            Token::Uint(chain_id.into()),
+            Token::Address(settlement_contract),
+        ]))))
+    }
+
+    /// Fails unless `contract` hashes a probe order like this domain does, so
+    /// that orders signed for it don't revert on-chain.
+    pub async fn check_deployment(&self, contract: &MooSettlementContract) -> Result<()> {
+        let probe = Order {
+            token_in: H160::repeat_byte(1),
+            amount_in: 100.into(),
+            token_out: H160::repeat_byte(2),
+            amount_out: 200.into(),
+            valid_to: u32::MAX.into(),
+            maker: H160::repeat_byte(3),
+            uid: Bytes(vec![4]),
+        };
+        let hash = contract
+            .generate_eip712_hash(probe.as_tuple())
+            .call()
+            .await
+            .context("failed to hash the probe order on-chain")?;
+        ensure!(
+            H256(hash.0) == order_hash(self, &probe),
+            "MooSettlementContract at {:?} hashes orders for another domain",
+            contract.address()
+        );
+        Ok(())
+    }
+}
+
+/// The EIP-712 struct hash of `order`, what `_hashOrder` computes.
+pub fn hash_struct(order: &Order) -> H256 {
+    H256(keccak256(&encode(&[
+        Token::FixedBytes(keccak256(ORDER_TYPE.as_bytes()).to_vec()),
+        Token::Address(order.token_in),
+        Token::Uint(order.amount_in),
+        Token::Address(order.token_out),
+        Token::Uint(order.amount_out),
+        Token::Uint(order.valid_to),
+        Token::Address(order.maker),
+        Token::FixedBytes(keccak256(&order.uid.0).to_vec()),
+    ])))
+}
+
+/// The EIP-712 hash of `order`, what `generateEIP712Hash` computes and
+/// `invalidatedOrders` is keyed by.
+pub fn order_hash(domain: &DomainSeparator, order: &Order) -> H256 {
+    typed_data_hash(domain, hash_struct(order))
+}
+
+/// The EIP-712 hash of the cancellation of the order with `uid`. The contract
+/// knows nothing of it, only the order book does.
+pub fn cancellation_hash(domain: &DomainSeparator, uid: &OrderUid) -> H256 {
+    let struct_hash = H256(keccak256(&encode(&[
+        Token::FixedBytes(keccak256(CANCELLATION_TYPE.as_bytes()).to_vec()),
+        Token::FixedBytes(keccak256(&uid.0).to_vec()),
+    ])));
+    typed_data_hash(domain, struct_hash)
+}
+
+fn typed_data_hash(domain: &DomainSeparator, struct_hash: H256) -> H256 {
+    let mut message = [0u8; 66];
+    message[..2].copy_from_slice(b"\x19\x01");
+    message[2..34].copy_from_slice(domain.0.as_bytes());
+    message[34..].copy_from_slice(struct_hash.as_bytes());
+    H256(keccak256(&message))
+}
+
+/// What `getEthSignedMessageHash` computes: the hash `personal_sign` signs
+/// for the 32 bytes of `hash`.
+pub fn eth_signed_message_hash(hash: H256) -> H256 {
+    signing::hash_message(hash.as_bytes())
+}
+
+/// The address that signed `hash`, what `recoverSigner` computes. Signatures
+/// are `r`, `s` and `v` of 27 or 28, packed into 65 bytes.
+pub fn recover_signer(hash: H256, signature: &[u8]) -> Result<H160> {
+    ensure!(signature.len() == 65, "signature is not 65 bytes long");
+    let recovery_id = match signature[64] {
+        v @ (27 | 28) => v - 27,
+        v => return Err(anyhow!("invalid signature v {v}")),
+    };
+    Ok(signing::recover(
+        hash.as_bytes(),
+        &signature[..64],
+        recovery_id.into(),
+    )?)
+}
+
+/// Fails unless the maker of the order signed it for `domain`.
+pub fn verify(domain: &DomainSeparator, signed: &SignedOrder) -> Result<()> {
+    let hash = eth_signed_message_hash(order_hash(domain, &signed.order));
+    let signer = recover_signer(hash, &signed.signature.0)?;
+    ensure!(
+        signer == signed.order.maker,
+        "maker order signed by {signer:?} instead of its maker {:?}",
+        signed.order.maker
+    );
+    Ok(())
+}
+
+/// Fails unless the maker of the order with `uid` signed its cancellation for
+/// `domain`.
+pub fn verify_cancellation(
+    domain: &DomainSeparator,
+    uid: &OrderUid,
+    signature: &[u8],
+) -> Result<()> {
+    let (_, maker, _) = uid.parts();
+    let hash = eth_signed_message_hash(cancellation_hash(domain, uid));
+    let signer = recover_signer(hash, signature)?;
+    ensure!(
+        signer == maker,
+        "cancellation of {uid} signed by {signer:?} instead of its maker {maker:?}"
+    );
+    Ok(())
+}
+
+/// Signs `order` for `domain` the way `verify` expects it.
+pub fn sign(domain: &DomainSeparator, order: &Order, key: impl Key) -> Result<Bytes> {
+    sign_hash(order_hash(domain, order), key)
+}
+
+/// Signs the cancellation of the order with `uid` for `domain` the way
+/// `verify_cancellation` expects it.
+pub fn sign_cancellation(domain: &DomainSeparator, uid: &OrderUid, key: impl Key) -> Result<Bytes> {
+    sign_hash(cancellation_hash(domain, uid), key)
+}
+
+fn sign_hash(hash: H256, key: impl Key) -> Result<Bytes> {
+    let hash = eth_signed_message_hash(hash);
+    let signature = key.sign(hash.as_bytes(), None)?;
+    let mut bytes = Vec::with_capacity(65);
+    bytes.extend_from_slice(signature.r.as_bytes());
+    bytes.extend_from_slice(signature.s.as_bytes());
+    bytes.push(signature.v as u8);
+    Ok(Bytes(bytes))
+}
+
+/// An order taking in `100 + nonce` of the first token for 200 of the second,
+/// with its uid and signed by a fixed test key.
+#[cfg(test)]
+pub(crate) fn test_order(domain: &DomainSeparator, nonce: u8, tokens: (H160, H160)) -> SignedOrder {
+    test_order_valid_to(domain, nonce, tokens, u32::MAX)
+}
+
+/// Like `test_order`, but only valid until `valid_to`.
+#[cfg(test)]
+pub(crate) fn test_order_valid_to(
+    domain: &DomainSeparator,
+    nonce: u8,
+    tokens: (H160, H160),
+    valid_to: u32,
+) -> SignedOrder {
+    let key = test_key();
+    let mut order = Order {
+        token_in: tokens.0,
+        amount_in: (100 + u64::from(nonce)).into(),
+        token_out: tokens.1,
+        amount_out: 200.into(),
+        valid_to: valid_to.into(),
+        maker: key.public_address(),
+        uid: Bytes::default(),
+    };
+    order.uid = Bytes(order.compute_uid(domain).unwrap().0.to_vec());
+    let signature = sign(domain, &order, &*key).unwrap();
+    SignedOrder { order, signature }
+}
+
+/// The key the maker of test orders signs with.
+#[cfg(test)]
+pub(crate) fn test_key() -> contracts::ethcontract::PrivateKey {
+    contracts::ethcontract::PrivateKey::from_raw([0x11; 32]).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain() -> DomainSeparator {
+        DomainSeparator(H256::repeat_byte(0xdd))
+    }
+
+    fn signed() -> SignedOrder {
+        test_order(&domain(), 1, (H160::repeat_byte(1), H160::repeat_byte(2)))
+    }
+
+    /// The maker order the solver was first tested with on Goerli.
+    fn goerli_order() -> SignedOrder {
+        SignedOrder {
+            order: Order {
+                token_in: "0x6778ac35e1c9aca22a8d7d820577212a89544df9".parse().unwrap(),
+                amount_in: 100_000_000_000_000_000u64.into(),
+                token_out: "0xb4fbf271143f4fbf7b91a5ded31805e42b2208d6".parse().unwrap(),
+                amount_out: 1_000_000_000_000_000_000u64.into(),
+                valid_to: 1_747_179_217.into(),
+                maker: "0xd1f5c19d7330F333F28A5CF3F391Bf679aC55841".parse().unwrap(),
+                uid: Bytes(vec![1]),
+            },
+            signature: Bytes(hex::decode("3bdf1180a463da09ffc18d45937e66767cb76044b85a6108751796c8ab8a01130f5c5dad1d5282a1f5880bbc6ddb8fe194e427523a5ea32ddf1d858a23113ffd1c").unwrap()),
+        }
+    }
+
+    fn h256(hex: &str) -> H256 {
+        hex.parse().unwrap()
+    }
+
+    /// The `Mail` example of EIP-712, signed by the key `keccak256("cow")`.
+    #[test]
+    fn hashes_and_recovers_the_eip712_example() {
+        let domain = DomainSeparator(h256(
+            "0xf2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f",
+        ));
+        let mail = h256("0xc52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e");
+        let hash = typed_data_hash(&domain, mail);
+        assert_eq!(
+            hash,
+            h256("0xbe609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2")
+        );
+        let signature = hex::decode(
+            "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+             07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562\
+             1c",
+        )
+        .unwrap();
+        assert_eq!(
+            recover_signer(hash, &signature).unwrap(),
+            "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    /// Pins the struct hash of `ORDER_TYPE`. Its signature only verifies with
+    /// the domain of the deployment, see `computes_what_the_contract_computes`.
+    #[test]
+    fn hashes_orders_like_the_goerli_order() {
+        assert_eq!(
+            hash_struct(&goerli_order().order),
+            h256("0x41386cefc4ca51b04611fd91760996e030848966f930fe5230eaaab7c613f2f5")
+        );
+    }
+
+    #[test]
+    fn separates_deployments() {
+        let contract = H160::repeat_byte(0xee);
+        let domain = DomainSeparator::new(5, contract);
+        assert_eq!(domain, DomainSeparator::new(5, contract));
+        assert_ne!(domain, DomainSeparator::new(1, contract));
+        assert_ne!(domain, DomainSeparator::new(5, H160::repeat_byte(0xef)));
+    }
+
+    #[test]
+    fn verifies_signatures_of_the_maker() {
+        let signed = signed();
+        verify(&domain(), &signed).unwrap();
+
+        // Signed for another deployment.
+        assert!(verify(&DomainSeparator(H256::repeat_byte(0xde)), &signed).is_err());
+        let mut tampered = signed.clone();
+        tampered.order.amount_out = 201.into();
+        assert!(verify(&domain(), &tampered).is_err());
+        let mut other_maker = signed.clone();
+        other_maker.order.maker = H160::repeat_byte(3);
+        assert!(verify(&domain(), &other_maker).is_err());
+        let mut truncated = signed;
+        truncated.signature.0.pop();
+        assert!(verify(&domain(), &truncated).is_err());
+    }
+
+    #[test]
+    fn verifies_cancellations_of_the_maker() {
+        let uid = signed().order.parse_uid().unwrap();
+        let signature = sign_cancellation(&domain(), &uid, &*test_key()).unwrap();
+        verify_cancellation(&domain(), &uid, &signature.0).unwrap();
+
+        let other_domain = DomainSeparator(H256::repeat_byte(0xde));
+        assert!(verify_cancellation(&other_domain, &uid, &signature.0).is_err());
+        let other_uid = test_order(&domain(), 2, (H160::repeat_byte(1), H160::repeat_byte(2)))
+            .order
+            .parse_uid()
+            .unwrap();
+        assert!(verify_cancellation(&domain(), &other_uid, &signature.0).is_err());
+        // Only its maker can cancel an order.
+        let other_key = contracts::ethcontract::PrivateKey::from_raw([0x22; 32]).unwrap();
+        let forged = sign_cancellation(&domain(), &uid, &*other_key).unwrap();
+        assert!(verify_cancellation(&domain(), &uid, &forged.0).is_err());
+        // An order signature is no cancellation.
+        assert!(verify_cancellation(&domain(), &uid, &signed().signature.0).is_err());
+    }
+
+    /// Needs a node that `MooSettlementContract` is deployed on, e.g.
+    /// `NODE_URL=https://goerli.infura.io/v3/<key> cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn computes_what_the_contract_computes() {
+        use contracts::ethcontract::Bytes as ContractBytes;
+
+        let node_url = std::env::var("NODE_URL").expect("Set NODE_URL env variable");
+        let web3 = web3::Web3::new(web3::transports::Http::new(&node_url).unwrap());
+        let address = crate::solve::MOO_SETTLEMENT_CONTRACT_ADDRESS
+            .parse()
+            .unwrap();
+        let contract = contracts::MooSettlementContract::at(&web3, address);
+        let chain_id = web3.eth().chain_id().await.unwrap().as_u64();
+        let domain = DomainSeparator::new(chain_id, address);
+        domain.check_deployment(&contract).await.unwrap();
+        verify(&domain, &goerli_order()).unwrap();
+        let signed = test_order(&domain, 1, (H160::repeat_byte(1), H160::repeat_byte(2)));
+        let order = &signed.order;
+
+        let struct_hash = contract.hash_order(order.as_tuple()).call().await.unwrap();
+        assert_eq!(H256(struct_hash.0), hash_struct(order));
+        let hash = contract
+            .generate_eip712_hash(order.as_tuple())
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(H256(hash.0), order_hash(&domain, order));
+        let message_hash = contract
+            .get_eth_signed_message_hash(hash)
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(H256(message_hash.0), eth_signed_message_hash(H256(hash.0)));
+        let signer = contract
+            .recover_signer(message_hash, ContractBytes(signed.signature.0.clone()))
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(signer, order.maker);
+    }
+}
@@ -0,0 +1,187 @@
+//! Routing user orders through the constant product pools of the auction.
+
+use super::{fixed_amount, satisfies_limit_price};
+use crate::models::batch_auction_model::{AmmModel, AmmParameters, OrderModel};
+use web3::types::{H160, U256};
+
+/// Fees are handled in parts per million of the input amount.
+const FEE_BASE: u64 = 1_000_000;
+
+/// A Uniswap V2 like pool, with its tokens in the order the pair contract
+/// sorts them.
+#[derive(Clone, Debug)]
+pub struct Pool {
+    pub id: usize,
+    pub address: H160,
+    pub tokens: (H160, H160),
+    reserves: (U256, U256),
+    fee: u64,
+}
+
+/// Swapping an exact amount in on a pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Swap {
+    pub pool: usize,
+    pub token_in: H160,
+    pub amount_in: U256,
+    pub token_out: H160,
+    pub amount_out: U256,
+}
+
+impl Pool {
+    /// `None` for pools that aren't constant product pools of two tokens.
+    pub fn from_model(id: usize, amm: &AmmModel) -> Option<Self> {
+        let reserves = match &amm.parameters {
+            AmmParameters::ConstantProduct(parameters) => &parameters.reserves,
+            AmmParameters::Unsupported => return None,
+        };
+        if reserves.len() != 2 || !(0. ..1.).contains(&amm.fee) {
+            return None;
+        }
+        // `BTreeMap` iterates in the same order the pair contract sorts by.
+        let mut reserves = reserves.iter();
+        let (token_0, reserve_0) = reserves.next()?;
+        let (token_1, reserve_1) = reserves.next()?;
+        Some(Self {
+            id,
+            address: amm.address,
+            tokens: (*token_0, *token_1),
+            reserves: (*reserve_0, *reserve_1),
+            fee: (amm.fee * FEE_BASE as f64).round() as u64,
+        })
+    }
+
+    pub fn trades(&self, token_in: H160, token_out: H160) -> bool {
+        self.tokens == (token_in, token_out) || self.tokens == (token_out, token_in)
+    }
+
+    fn reserves(&self, token_in: H160) -> (U256, U256) {
+        if token_in == self.tokens.0 {
+            self.reserves
+        } else {
+            (self.reserves.1, self.reserves.0)
+        }
+    }
+
+    /// What the pool pays out for `amount_in`, the way `getAmountOut` of the
+    /// Uniswap V2 library computes it.
+    pub fn amount_out(&self, token_in: H160, amount_in: U256) -> Option<U256> {
+        let (reserve_in, reserve_out) = self.reserves(token_in);
+        let amount_in_with_fee = amount_in.checked_mul((FEE_BASE - self.fee).into())?;
+        let numerator = amount_in_with_fee.full_mul(reserve_out);
+        let denominator =
+            reserve_in.full_mul(FEE_BASE.into()) + amount_in_with_fee.full_mul(1.into());
+        if denominator.is_zero() {
+            return None;
+        }
+        U256::try_from(numerator / denominator).ok()
+    }
+
+    /// What the pool takes in for paying out `amount_out`, the way
+    /// `getAmountIn` of the Uniswap V2 library computes it.
+    pub fn amount_in(&self, token_out: H160, amount_out: U256) -> Option<U256> {
+        let (reserve_out, reserve_in) = self.reserves(token_out);
+        if amount_out >= reserve_out {
+            return None;
+        }
+        let numerator = reserve_in
+            .full_mul(amount_out)
+            .checked_mul(U256::from(FEE_BASE).full_mul(1.into()))?;
+        let denominator = (reserve_out - amount_out).full_mul((FEE_BASE - self.fee).into());
+        U256::try_from(numerator / denominator)
+            .ok()?
+            .checked_add(1.into())
+    }
+
+    /// The output of a swap in the pair contract's `token0`, `token1` order.
+    pub fn amounts_out(&self, swap: &Swap) -> (U256, U256) {
+        if swap.token_out == self.tokens.0 {
+            (swap.amount_out, U256::zero())
+        } else {
+            (U256::zero(), swap.amount_out)
+        }
+    }
+}
+
+/// Routes `order` in full through the pool that gives it the best price, as
+/// long as that is at least its limit price.
+pub fn route(order: &OrderModel, pools: &[&Pool]) -> Option<Swap> {
+    let (token_in, token_out) = (order.sell_token, order.buy_token);
+    let swaps = pools
+        .iter()
+        .filter(|pool| pool.trades(token_in, token_out))
+        .filter_map(|pool| {
+            let (amount_in, amount_out) = if order.is_sell_order {
+                (
+                    order.sell_amount,
+                    pool.amount_out(token_in, order.sell_amount)?,
+                )
+            } else {
+                (
+                    pool.amount_in(token_out, order.buy_amount)?,
+                    order.buy_amount,
+                )
+            };
+            Some(Swap {
+                pool: pool.id,
+                token_in,
+                amount_in,
+                token_out,
+                amount_out,
+            })
+        });
+    let best = if order.is_sell_order {
+        swaps.max_by_key(|swap| swap.amount_out)
+    } else {
+        swaps.min_by_key(|swap| swap.amount_in)
+    }?;
+    (!best.amount_out.is_zero()
+        && !fixed_amount(order).is_zero()
+        && satisfies_limit_price(order, best.amount_in, best.amount_out))
+    .then_some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::batch_auction_model::{ConstantProductPoolParameters, CostModel};
+    use crate::solve::fixtures::{buy_order, order, token};
+
+    fn pool(id: usize, reserve_1: u64, reserve_2: u64) -> Pool {
+        let amm = AmmModel {
+            parameters: AmmParameters::ConstantProduct(ConstantProductPoolParameters {
+                reserves: [(token(2), reserve_2.into()), (token(1), reserve_1.into())].into(),
+            }),
+            fee: 0.003,
+            cost: CostModel::default(),
+            mandatory: false,
+            address: H160::from_low_u64_be(id as u64),
+        };
+        Pool::from_model(id, &amm).unwrap()
+    }
+
+    #[test]
+    fn computes_amounts_like_uniswap() {
+        let pool = pool(0, 1_000_000, 2_000_000);
+        assert_eq!(pool.tokens, (token(1), token(2)));
+        // 1000 * 997 * 2_000_000 / (1_000_000 * 1000 + 1000 * 997)
+        assert_eq!(pool.amount_out(token(1), 1000.into()), Some(1992.into()));
+        // 1_000_000 * 1992 * 1000 / ((2_000_000 - 1992) * 997) + 1
+        assert_eq!(pool.amount_in(token(2), 1992.into()), Some(1000.into()));
+        assert_eq!(pool.amount_in(token(2), 2_000_000.into()), None);
+    }
+
+    #[test]
+    fn routes_through_the_best_pool() {
+        let pools = [pool(0, 1_000_000, 2_000_000), pool(1, 1_000_000, 3_000_000)];
+        let pools = pools.iter().collect::<Vec<_>>();
+
+        let swap = route(&order(1000, 2000, false), &pools).unwrap();
+        assert_eq!((swap.pool, swap.amount_out), (1, 2988.into()));
+        let swap = route(&buy_order(1000, 2000, false), &pools).unwrap();
+        assert_eq!((swap.pool, swap.amount_in), (1, 670.into()));
+
+        assert!(route(&order(1000, 3000, false), &pools).is_none());
+        assert!(route(&buy_order(500, 2000, false), &pools).is_none());
+    }
+}
@@ -0,0 +1,88 @@
+//! Exact arithmetic on token amounts and prices. Products are taken in 512
+//! bits so they can't overflow, every division states which way it rounds,
+//! and results that don't fit are errors rather than panics.
+
+use std::fmt::{self, Display, Formatter};
+use web3::types::U256;
+
+/// Which way to round the result of a division.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    Overflow,
+}
+
+impl Display for ArithmeticError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ArithmeticError::DivisionByZero => f.write_str("division by zero"),
+            ArithmeticError::Overflow => f.write_str("result doesn't fit into 256 bits"),
+        }
+    }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+/// `a * b / c`, rounded as given.
+pub fn mul_div(a: U256, b: U256, c: U256, rounding: Rounding) -> Result<U256, ArithmeticError> {
+    if c.is_zero() {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+    // Multiplying by one widens `c` to the 512 bit type of the product.
+    let c = c.full_mul(U256::one());
+    let product = a.full_mul(b);
+    let quotient = U256::try_from(product / c).map_err(|_| ArithmeticError::Overflow)?;
+    match rounding {
+        Rounding::Up if !(product % c).is_zero() => quotient
+            .checked_add(U256::one())
+            .ok_or(ArithmeticError::Overflow),
+        _ => Ok(quotient),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_in_the_given_direction() {
+        assert_eq!(
+            mul_div(7.into(), 3.into(), 2.into(), Rounding::Down),
+            Ok(10.into())
+        );
+        assert_eq!(
+            mul_div(7.into(), 3.into(), 2.into(), Rounding::Up),
+            Ok(11.into())
+        );
+        assert_eq!(
+            mul_div(8.into(), 3.into(), 2.into(), Rounding::Up),
+            Ok(12.into())
+        );
+    }
+
+    #[test]
+    fn takes_products_in_512_bits() {
+        assert_eq!(
+            mul_div(U256::MAX, U256::MAX, U256::MAX, Rounding::Up),
+            Ok(U256::MAX)
+        );
+        assert_eq!(
+            mul_div(U256::MAX, 2.into(), 1.into(), Rounding::Down),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            mul_div(U256::MAX, 1.into(), 1.into(), Rounding::Up),
+            Ok(U256::MAX)
+        );
+        assert_eq!(
+            mul_div(1.into(), 1.into(), 0.into(), Rounding::Down),
+            Err(ArithmeticError::DivisionByZero)
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! Coincidence of wants: settling two user orders that trade in opposite
+//! directions directly against each other.
+
+use super::arithmetic::{mul_div, Rounding};
+use super::quote::Quote;
+use super::{execute, fixed_amount, satisfies_limit_price};
+use crate::models::batch_auction_model::OrderModel;
+use web3::types::{H160, U256};
+
+/// Two user orders settled against each other at a uniform clearing price.
+/// Whatever the smaller order can't absorb of the bigger one is filled by a
+/// quote, or left unfilled if the bigger order is partially fillable.
+#[derive(Debug)]
+pub struct CowMatch<'a> {
+    /// Executed sell and buy amounts of the two orders, in the order they
+    /// were passed to `match_orders`.
+    pub executed: [(U256, U256); 2],
+    pub residual: Option<Quote<'a>>,
+}
+
+/// A clearing price from the first order's point of view: an amount of its
+/// sell token and the amount of its buy token that is worth the same.
+type Price = (U256, U256);
+
+pub fn is_opposite(first: &OrderModel, second: &OrderModel) -> bool {
+    first.sell_token == second.buy_token && first.buy_token == second.sell_token
+}
+
+/// Matches `first` against `second`, using one of `quotes` for the residual if
+/// they can't be settled against each other completely. Only if no quote fits
+/// a partially fillable order gets filled as far as the other order absorbs
+/// it.
+///
+/// Sell orders trade their whole sell amount and buy orders their whole buy
+/// amount, so the candidate clearing prices are the ones that balance either
+/// token in the settlement, followed by the price in the middle of both limit
+/// prices and the limit prices themselves. The first one that clears wins.
+pub fn match_orders<'a>(
+    first: &OrderModel,
+    second: &OrderModel,
+    quotes: &[Quote<'a>],
+) -> Option<CowMatch<'a>> {
+    if !is_opposite(first, second) {
+        return None;
+    }
+    let limit_prices = limit_prices(first, second)?;
+    let full = (fixed_amount(first), fixed_amount(second));
+    let clear_with = |residual: Option<Quote<'a>>| {
+        balancing_prices(first, second, residual)
+            .into_iter()
+            .chain(limit_prices)
+            .find_map(|price| clear(first, second, full, price, residual))
+    };
+
+    if let Some(cow_match) = clear_with(None) {
+        return Some(cow_match);
+    }
+
+    let mut quotes = quotes
+        .iter()
+        .filter(|quote| {
+            trades(quote, first.sell_token, first.buy_token)
+                || trades(quote, second.sell_token, second.buy_token)
+        })
+        .copied()
+        .collect::<Vec<_>>();
+    quotes.sort_by_key(|quote| std::cmp::Reverse(quote.amount_out()));
+    if let Some(cow_match) = quotes.into_iter().find_map(|quote| clear_with(Some(quote))) {
+        return Some(cow_match);
+    }
+
+    limit_prices.into_iter().find_map(|price| {
+        let (first_sell, first_buy) = execute(first, full.0, price).ok()?;
+        let (second_sell, second_buy) = execute(second, full.1, (price.1, price.0)).ok()?;
+        if first.allow_partial_fill && second_buy <= first_sell && second_sell <= first_buy {
+            let partial = if first.is_sell_order {
+                second_buy
+            } else {
+                second_sell
+            };
+            return clear(first, second, (partial, full.1), price, None);
+        }
+        if second.allow_partial_fill && first_buy <= second_sell && first_sell <= second_buy {
+            let partial = if second.is_sell_order {
+                first_buy
+            } else {
+                first_sell
+            };
+            return clear(first, second, (full.0, partial), price, None);
+        }
+        None
+    })
+}
+
+fn trades(quote: &Quote, token_in: H160, token_out: H160) -> bool {
+    quote.token_in() == token_in && quote.token_out() == token_out
+}
+
+/// The price in the middle of the limit prices of both orders followed by
+/// the limit prices themselves, or `None` if no price satisfies both.
+fn limit_prices(first: &OrderModel, second: &OrderModel) -> Option<[Price; 3]> {
+    let first_limit = first.buy_amount;
+    // What the second order still accepts for the same amount of the first
+    // order's sell token.
+    let second_limit = mul_div(
+        second.sell_amount,
+        first.sell_amount,
+        second.buy_amount,
+        Rounding::Down,
+    )
+    .ok()?;
+    if first_limit > second_limit {
+        return None;
+    }
+    let middle = first_limit + (second_limit - first_limit) / 2;
+    Some([middle, first_limit, second_limit].map(|buy_amount| (first.sell_amount, buy_amount)))
+}
+
+/// The prices at which the settlement takes in exactly as much of one of the
+/// tokens as it pays out, with `quote` filling the residual. Each order
+/// trades a fixed amount of one token and an amount of the other one that
+/// depends on the price, which makes either balance a linear equation.
+fn balancing_prices(first: &OrderModel, second: &OrderModel, quote: Option<Quote>) -> Vec<Price> {
+    let quote_out = |token: H160| {
+        quote
+            .filter(|quote| quote.token_out() == token)
+            .map_or(U256::zero(), |quote| quote.amount_out())
+    };
+    let quote_in = |token: H160| {
+        quote
+            .filter(|quote| quote.token_in() == token)
+            .map_or(U256::zero(), |quote| quote.amount_in())
+    };
+    // The sell amount of sell orders and the buy amount of buy orders, the
+    // other one being zero.
+    let sold = |order: &OrderModel| {
+        if order.is_sell_order {
+            order.sell_amount
+        } else {
+            U256::zero()
+        }
+    };
+    let bought = |order: &OrderModel| {
+        if order.is_sell_order {
+            U256::zero()
+        } else {
+            order.buy_amount
+        }
+    };
+
+    let mut prices = Vec::new();
+    // In the first order's buy token, with the price as buy per sell amount:
+    // price * (sold(first) - bought(second))
+    //     == sold(second) + quote_out - bought(first) - quote_in
+    if let Some((buy_amount, sell_amount)) = solve_linear(
+        (
+            sold(second).checked_add(quote_out(first.buy_token)),
+            bought(first).checked_add(quote_in(first.buy_token)),
+        ),
+        (sold(first), bought(second)),
+    ) {
+        prices.push((sell_amount, buy_amount));
+    }
+    // In the first order's sell token, with the price as sell per buy amount:
+    // price * (bought(first) - sold(second))
+    //     == bought(second) + quote_in - sold(first) - quote_out
+    if let Some((sell_amount, buy_amount)) = solve_linear(
+        (
+            bought(second).checked_add(quote_in(first.sell_token)),
+            sold(first).checked_add(quote_out(first.sell_token)),
+        ),
+        (bought(first), sold(second)),
+    ) {
+        prices.push((sell_amount, buy_amount));
+    }
+    prices
+}
+
+/// Solves `x * (denominator.0 - denominator.1) == numerator.0 - numerator.1`
+/// for a positive `x`, returned as a fraction.
+fn solve_linear(
+    numerator: (Option<U256>, Option<U256>),
+    denominator: (U256, U256),
+) -> Option<(U256, U256)> {
+    match (numerator.0?, numerator.1?, denominator.0, denominator.1) {
+        (n0, n1, d0, d1) if n0 > n1 && d0 > d1 => Some((n0 - n1, d0 - d1)),
+        (n0, n1, d0, d1) if n0 < n1 && d0 < d1 => Some((n1 - n0, d1 - d0)),
+        _ => None,
+    }
+}
+
+/// Executes the orders for their `fixed` amounts at `price` and checks that
+/// they get at least their limit price and that the settlement contract
+/// doesn't pay out more of either token than it takes in.
+fn clear<'a>(
+    first: &OrderModel,
+    second: &OrderModel,
+    fixed: (U256, U256),
+    price: Price,
+    residual: Option<Quote<'a>>,
+) -> Option<CowMatch<'a>> {
+    let (first_sell, first_buy) = execute(first, fixed.0, price).ok()?;
+    let (second_sell, second_buy) = execute(second, fixed.1, (price.1, price.0)).ok()?;
+    if first_sell.is_zero()
+        || second_sell.is_zero()
+        || !satisfies_limit_price(first, first_sell, first_buy)
+        || !satisfies_limit_price(second, second_sell, second_buy)
+    {
+        return None;
+    }
+
+    // What the quote pays into and takes out of the settlement contract.
+    let quote_out = |token: H160| {
+        residual
+            .filter(|quote| quote.token_out() == token)
+            .map_or(U256::zero(), |quote| quote.amount_out())
+    };
+    let quote_in = |token: H160| {
+        residual
+            .filter(|quote| quote.token_in() == token)
+            .map_or(U256::zero(), |quote| quote.amount_in())
+    };
+    let conserves = |token: H160, sold: U256, bought: U256| match (
+        sold.checked_add(quote_out(token)),
+        bought.checked_add(quote_in(token)),
+    ) {
+        (Some(incoming), Some(outgoing)) => incoming >= outgoing,
+        _ => false,
+    };
+    if !conserves(first.sell_token, first_sell, second_buy)
+        || !conserves(second.sell_token, second_sell, first_buy)
+    {
+        return None;
+    }
+
+    Some(CowMatch {
+        executed: [(first_sell, first_buy), (second_sell, second_buy)],
+        residual,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solve::fixtures::{self, maker_order, token, trading};
+
+    fn order(sell_token: H160, buy_token: H160, sell_amount: u64, buy_amount: u64) -> OrderModel {
+        trading(
+            fixtures::order(sell_amount, buy_amount, false),
+            sell_token,
+            buy_token,
+        )
+    }
+
+    fn buy_order(
+        sell_token: H160,
+        buy_token: H160,
+        sell_amount: u64,
+        buy_amount: u64,
+    ) -> OrderModel {
+        trading(
+            fixtures::buy_order(sell_amount, buy_amount, false),
+            sell_token,
+            buy_token,
+        )
+    }
+
+    #[test]
+    fn matches_orders_completely() {
+        let first = order(token(1), token(2), 100, 190);
+        let second = order(token(2), token(1), 200, 95);
+        let cow_match = match_orders(&first, &second, &[]).unwrap();
+        assert_eq!(
+            cow_match.executed,
+            [(100.into(), 200.into()), (200.into(), 100.into())]
+        );
+        assert!(cow_match.residual.is_none());
+    }
+
+    #[test]
+    fn matches_buy_orders_completely() {
+        // Both orders buy exactly what the other one pays.
+        let first = buy_order(token(1), token(2), 110, 200);
+        let second = buy_order(token(2), token(1), 210, 100);
+        let cow_match = match_orders(&first, &second, &[]).unwrap();
+        assert_eq!(
+            cow_match.executed,
+            [(100.into(), 200.into()), (200.into(), 100.into())]
+        );
+
+        // Any price balances a sell order against a buy order of the same
+        // amount, so they meet in the middle of their limit prices.
+        let first = order(token(1), token(2), 100, 150);
+        let second = buy_order(token(2), token(1), 250, 100);
+        let cow_match = match_orders(&first, &second, &[]).unwrap();
+        assert_eq!(
+            cow_match.executed,
+            [(100.into(), 200.into()), (200.into(), 100.into())]
+        );
+    }
+
+    #[test]
+    fn fills_residual_with_maker_order() {
+        // The first order sells 100 but the second only wants 50 of it.
+        let first = order(token(1), token(2), 100, 190);
+        let second = order(token(2), token(1), 100, 50);
+        let maker = maker_order(1, 50, 100);
+        let cow_match = match_orders(&first, &second, &[Quote::Maker(&maker)]).unwrap();
+        assert_eq!(
+            cow_match.executed,
+            [(100.into(), 200.into()), (100.into(), 50.into())]
+        );
+        assert!(cow_match.residual.is_some());
+    }
+
+    #[test]
+    fn fills_residual_of_buy_order_with_maker_order() {
+        // The first order buys 200 but the second only sells 100.
+        let first = buy_order(token(1), token(2), 110, 200);
+        let second = order(token(2), token(1), 100, 40);
+        let maker = maker_order(1, 50, 100);
+        let cow_match = match_orders(&first, &second, &[Quote::Maker(&maker)]).unwrap();
+        assert_eq!(
+            cow_match.executed,
+            [(100.into(), 200.into()), (100.into(), 50.into())]
+        );
+        assert!(cow_match.residual.is_some());
+    }
+
+    #[test]
+    fn respects_limit_prices() {
+        let first = order(token(1), token(2), 100, 300);
+        let second = order(token(2), token(1), 200, 100);
+        assert!(match_orders(&first, &second, &[]).is_none());
+        assert!(match_orders(&first, &order(token(1), token(3), 200, 100), &[]).is_none());
+
+        let first = buy_order(token(1), token(2), 90, 200);
+        let second = buy_order(token(2), token(1), 190, 100);
+        assert!(match_orders(&first, &second, &[]).is_none());
+    }
+
+    #[test]
+    fn fills_partially_fillable_order_as_far_as_the_other_absorbs() {
+        // For its 100, the second order takes at least 50 of the first
+        // order's sell token, and the first one gives at most 133. Rounding
+        // in favour of both of them leaves the settlement short at the prices
+        // in between, so they meet at the second order's limit price.
+        let mut first = order(token(1), token(2), 200, 150);
+        let second = order(token(2), token(1), 100, 50);
+        assert!(match_orders(&first, &second, &[]).is_none());
+
+        first.allow_partial_fill = true;
+        let cow_match = match_orders(&first, &second, &[]).unwrap();
+        assert_eq!(
+            cow_match.executed,
+            [(50.into(), 100.into()), (100.into(), 50.into())]
+        );
+    }
+
+    #[test]
+    fn prefers_maker_orders_over_partial_fills() {
+        let mut first = order(token(1), token(2), 100, 190);
+        first.allow_partial_fill = true;
+        let second = order(token(2), token(1), 100, 50);
+        let maker = maker_order(1, 50, 100);
+        let cow_match = match_orders(&first, &second, &[Quote::Maker(&maker)]).unwrap();
+        assert_eq!(cow_match.executed[0], (100.into(), 200.into()));
+        assert!(cow_match.residual.is_some());
+    }
+
+    #[test]
+    fn rejects_maker_orders_that_leave_a_deficit() {
+        let first = order(token(1), token(2), 100, 190);
+        let second = order(token(2), token(1), 100, 50);
+        // Takes more of the first order's sell token than is left over.
+        let maker = maker_order(1, 60, 100);
+        assert!(match_orders(&first, &second, &[Quote::Maker(&maker)]).is_none());
+    }
+}
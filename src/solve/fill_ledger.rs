@@ -0,0 +1,236 @@
+use crate::models::batch_auction_model::AuctionResult;
+use crate::models::settlement_contract_data::OrderUid;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Where a maker order we put into a solution is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillState {
+    /// Part of a solution we proposed, outcome unknown.
+    Proposed,
+    /// Part of a winning solution that is being submitted.
+    Won,
+    /// Consumed on-chain.
+    Settled,
+    /// Its `valid_to` has passed.
+    Expired,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    /// Every auction the order was proposed in whose outcome is still open.
+    auctions: HashSet<Option<u64>>,
+    /// The auction the order was won in, while `state` is `Won`.
+    won_in: Option<u64>,
+    state: FillState,
+}
+
+/// Tracks the maker orders the solver has used, keyed by maker order uid, so
+/// that an order is not reused once it is won, settled or expired. Orders
+/// expire at the `valid_to` their uid carries.
+#[derive(Debug, Default)]
+pub struct FillLedger {
+    entries: Mutex<HashMap<OrderUid, Entry>>,
+}
+
+impl FillLedger {
+    /// Whether the maker order with `uid` can still be put into a solution.
+    /// Orders that were only proposed are released again since we don't know
+    /// whether we won that auction until the driver tells us.
+    pub fn is_available(&self, uid: &OrderUid) -> bool {
+        match self.entries.lock().unwrap().get(uid) {
+            None => true,
+            Some(entry) => entry.state == FillState::Proposed,
+        }
+    }
+
+    pub fn state(&self, uid: &OrderUid) -> Option<FillState> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(uid)
+            .map(|entry| entry.state)
+    }
+
+    /// Records that the maker order was proposed in a solution for
+    /// `auction_id`, on top of the auctions it was already proposed in.
+    pub fn propose(&self, auction_id: Option<u64>, uid: OrderUid) {
+        self.entries
+            .lock()
+            .unwrap()
+            .entry(uid)
+            .or_insert(Entry {
+                auctions: HashSet::new(),
+                won_in: None,
+                state: FillState::Proposed,
+            })
+            .auctions
+            .insert(auction_id);
+    }
+
+    /// Records that the maker order was found consumed on-chain.
+    pub fn settle(&self, uid: OrderUid) {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(uid).or_insert(Entry {
+            auctions: HashSet::new(),
+            won_in: None,
+            state: FillState::Settled,
+        });
+        entry.state = FillState::Settled;
+    }
+
+    /// Applies the driver's verdict on `auction_id` to the maker orders we
+    /// proposed for it. Winning one auction takes an order out of all others
+    /// it was proposed in, while losing one only releases the order from that
+    /// auction.
+    pub fn notify(&self, auction_id: u64, result: &AuctionResult) {
+        let mut entries = self.entries.lock().unwrap();
+        let in_auction = entries
+            .iter_mut()
+            .filter(|(_, entry)| entry.auctions.contains(&Some(auction_id)));
+        match result {
+            AuctionResult::Won => {
+                for (_, entry) in in_auction {
+                    if entry.state == FillState::Proposed {
+                        entry.state = FillState::Won;
+                        entry.won_in = Some(auction_id);
+                    }
+                }
+            }
+            AuctionResult::Settled(_) => {
+                for (_, entry) in in_auction {
+                    entry.auctions.remove(&Some(auction_id));
+                    if matches!(entry.state, FillState::Proposed | FillState::Won) {
+                        entry.state = FillState::Settled;
+                        entry.won_in = None;
+                    }
+                }
+            }
+            AuctionResult::Rejected | AuctionResult::Failed => {
+                let mut released = Vec::new();
+                for (uid, entry) in in_auction {
+                    entry.auctions.remove(&Some(auction_id));
+                    if entry.won_in == Some(auction_id) {
+                        entry.state = FillState::Proposed;
+                        entry.won_in = None;
+                    }
+                    if entry.state == FillState::Proposed && entry.auctions.is_empty() {
+                        released.push(*uid);
+                    }
+                }
+                for uid in released {
+                    entries.remove(&uid);
+                }
+            }
+        }
+    }
+
+    /// Marks every order whose `valid_to` is before `now` as expired. Orders
+    /// that were already expired on a previous sweep are forgotten, the solver
+    /// never picks orders past their `valid_to` anyway.
+    pub fn expire(&self, now: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.state != FillState::Expired);
+        for (uid, entry) in entries.iter_mut() {
+            if u64::from(uid.valid_to()) < now {
+                entry.state = FillState::Expired;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uid(byte: u8) -> OrderUid {
+        uid_valid_to(byte, u32::MAX)
+    }
+
+    fn uid_valid_to(byte: u8, valid_to: u32) -> OrderUid {
+        let mut uid = [byte; 56];
+        uid[52..].copy_from_slice(&valid_to.to_be_bytes());
+        OrderUid(uid)
+    }
+
+    #[test]
+    fn proposed_orders_stay_available() {
+        let ledger = FillLedger::default();
+        ledger.propose(Some(1), uid(1));
+        assert!(ledger.is_available(&uid(1)));
+        assert_eq!(ledger.state(&uid(1)), Some(FillState::Proposed));
+    }
+
+    #[test]
+    fn won_and_settled_orders_are_not_reused() {
+        let ledger = FillLedger::default();
+        ledger.propose(Some(1), uid(1));
+        ledger.propose(Some(2), uid(2));
+
+        ledger.notify(1, &AuctionResult::Won);
+        assert!(!ledger.is_available(&uid(1)));
+        assert!(ledger.is_available(&uid(2)));
+
+        ledger.notify(1, &AuctionResult::Settled(Default::default()));
+        assert_eq!(ledger.state(&uid(1)), Some(FillState::Settled));
+        assert!(!ledger.is_available(&uid(1)));
+    }
+
+    #[test]
+    fn failed_auctions_release_their_orders() {
+        let ledger = FillLedger::default();
+        ledger.propose(Some(1), uid(1));
+        ledger.notify(1, &AuctionResult::Won);
+        ledger.notify(1, &AuctionResult::Failed);
+        assert_eq!(ledger.state(&uid(1)), None);
+        assert!(ledger.is_available(&uid(1)));
+    }
+
+    #[test]
+    fn expired_orders_are_not_reused() {
+        let ledger = FillLedger::default();
+        ledger.propose(Some(1), uid_valid_to(1, 100));
+        ledger.propose(Some(1), uid_valid_to(2, 300));
+        ledger.expire(200);
+        assert_eq!(
+            ledger.state(&uid_valid_to(1, 100)),
+            Some(FillState::Expired)
+        );
+        assert!(!ledger.is_available(&uid_valid_to(1, 100)));
+        assert!(ledger.is_available(&uid_valid_to(2, 300)));
+    }
+
+    #[test]
+    fn orders_proposed_in_several_auctions_keep_every_outcome() {
+        let ledger = FillLedger::default();
+        ledger.propose(Some(1), uid(1));
+        ledger.propose(Some(2), uid(1));
+        ledger.notify(1, &AuctionResult::Won);
+        assert!(!ledger.is_available(&uid(1)));
+        // Losing the other auction doesn't release the order that was won.
+        ledger.notify(2, &AuctionResult::Rejected);
+        assert_eq!(ledger.state(&uid(1)), Some(FillState::Won));
+        ledger.notify(1, &AuctionResult::Settled(Default::default()));
+        assert_eq!(ledger.state(&uid(1)), Some(FillState::Settled));
+        // Nor does a later proposal.
+        ledger.propose(Some(3), uid(1));
+        assert_eq!(ledger.state(&uid(1)), Some(FillState::Settled));
+
+        ledger.propose(Some(1), uid(2));
+        ledger.propose(Some(2), uid(2));
+        ledger.notify(1, &AuctionResult::Rejected);
+        assert_eq!(ledger.state(&uid(2)), Some(FillState::Proposed));
+        ledger.notify(2, &AuctionResult::Won);
+        ledger.notify(2, &AuctionResult::Failed);
+        assert_eq!(ledger.state(&uid(2)), None);
+
+        // A failed win falls back to the auctions still open.
+        ledger.propose(Some(1), uid(3));
+        ledger.propose(Some(2), uid(3));
+        ledger.notify(1, &AuctionResult::Won);
+        ledger.notify(1, &AuctionResult::Failed);
+        assert_eq!(ledger.state(&uid(3)), Some(FillState::Proposed));
+        ledger.notify(2, &AuctionResult::Rejected);
+        assert_eq!(ledger.state(&uid(3)), None);
+    }
+}
@@ -0,0 +1,97 @@
+//! What the tests of the solver build their auctions from.
+
+use super::risk::RiskLimits;
+use super::slippage::SlippagePolicy;
+use super::Auction;
+use crate::models::batch_auction_model::{CostModel, FeeModel, OrderModel, TokenInfoModel};
+use crate::models::settlement_contract_data::{Order, SignedOrder};
+use std::collections::{BTreeMap, HashSet};
+use web3::types::{Bytes, H160};
+
+pub fn token(byte: u8) -> H160 {
+    H160::repeat_byte(byte)
+}
+
+/// Stands in for `WETH9`.
+pub fn weth() -> H160 {
+    token(9)
+}
+
+/// Sells `sell_amount` of token 1 for at least `buy_amount` of token 2, for a
+/// fee of 10 of token 1.
+pub fn order(sell_amount: u64, buy_amount: u64, allow_partial_fill: bool) -> OrderModel {
+    OrderModel {
+        sell_token: token(1),
+        buy_token: token(2),
+        sell_amount: sell_amount.into(),
+        buy_amount: buy_amount.into(),
+        allow_partial_fill,
+        is_sell_order: true,
+        fee: FeeModel {
+            amount: 10.into(),
+            token: token(1),
+        },
+        cost: CostModel::default(),
+        is_liquidity_order: false,
+    }
+}
+
+/// Buys `buy_amount` of token 2 for at most `sell_amount` of token 1.
+pub fn buy_order(sell_amount: u64, buy_amount: u64, allow_partial_fill: bool) -> OrderModel {
+    OrderModel {
+        is_sell_order: false,
+        ..order(sell_amount, buy_amount, allow_partial_fill)
+    }
+}
+
+/// `order` selling `sell_token` for `buy_token` instead, with its fee in
+/// `sell_token`.
+pub fn trading(order: OrderModel, sell_token: H160, buy_token: H160) -> OrderModel {
+    OrderModel {
+        sell_token,
+        buy_token,
+        fee: FeeModel {
+            token: sell_token,
+            ..order.fee
+        },
+        ..order
+    }
+}
+
+/// Takes in `amount_in` of token 1 for `amount_out` of token 2, told apart
+/// from other maker orders by its one byte uid.
+pub fn maker_order(uid: u8, amount_in: u64, amount_out: u64) -> SignedOrder {
+    SignedOrder {
+        order: Order {
+            token_in: token(1),
+            amount_in: amount_in.into(),
+            token_out: token(2),
+            amount_out: amount_out.into(),
+            uid: Bytes(vec![uid]),
+            ..Default::default()
+        },
+        signature: Default::default(),
+    }
+}
+
+/// An auction of `orders` trading tokens 1 and 2, which are both worth 1 in
+/// the native token. Prices are normalized to 1_000_000 for token 1.
+pub(super) fn auction(orders: Vec<OrderModel>) -> Auction {
+    let info = TokenInfoModel {
+        external_price: Some(1.),
+        ..Default::default()
+    };
+    Auction {
+        orders: orders.into_iter().enumerate().collect(),
+        tokens: BTreeMap::from([(token(1), info.clone()), (token(2), info)]),
+        amms: BTreeMap::new(),
+        ref_token: token(1),
+        ref_token_price: 1_000_000.into(),
+        max_nr_exec_orders: None,
+        gas_price: None,
+        slippage: SlippagePolicy::default(),
+        risk_limits: RiskLimits::default(),
+        weth: weth(),
+        buys_native: HashSet::new(),
+    }
+}
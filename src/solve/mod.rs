@@ -1,161 +1,1924 @@
+mod amm;
+mod arithmetic;
+mod cow;
+pub mod fill_ledger;
+#[cfg(test)]
+pub(crate) mod fixtures;
+mod objective;
+mod prices;
+mod quote;
+pub mod risk;
+pub mod slippage;
+pub mod strategy;
+mod validate;
+
 use crate::interactions::settlement_contract::MooSettlementInteraction;
-use crate::interactions::Interaction;
+use crate::interactions::uniswap_v2::UniswapV2Interaction;
+use crate::interactions::weth::WethInteraction;
+use crate::interactions::{EncodedInteraction, Interaction};
 use crate::models::batch_auction_model::{
-    ApprovalModel, BatchAuctionModel, ExecutedOrderModel, InteractionData, OrderModel,
+    AmmModel, ApprovalModel, BatchAuctionModel, ExecutedOrderModel, ExecutionPlan,
+    ExecutionPlanCoordinatesModel, InteractionData, OrderModel, RankedSolutionModel,
     SettledBatchAuctionModel, TokenAmount, TokenInfoModel,
 };
-use crate::models::settlement_contract_data::Order;
+use crate::models::settlement_contract_data::SignedOrder;
+use crate::order_book::{signature, OrderBook};
 use anyhow::{anyhow, Result};
-use contracts::ethcontract::Bytes;
-use contracts::MooSettlementContract;
-use hex::FromHex;
-use std::collections::{BTreeMap, HashMap};
+use arithmetic::{mul_div, Rounding};
+use contracts::ethcontract::Bytes as ContractBytes;
+use contracts::{IUniswapLikePair, MooSettlementContract, ERC20, WETH9};
+use fill_ledger::FillLedger;
+use futures::stream::{FuturesUnordered, StreamExt};
+use quote::{Quote, QuoteId};
+use risk::{Exposure, RiskLimits};
+use slippage::SlippagePolicy;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use strategy::{CandidateSolution, ChainContext, SolverStrategy};
+use tokio::time::Instant;
 use web3::transports::Http;
-use web3::types::{H160, U256};
+use web3::types::{H160, H256, U256};
 use web3::Web3;
 
-static ALREADY_EXECUTED: AtomicBool = AtomicBool::new(false);
 // const MOO_SETTLEMENT_CONTRACT_ADDRESS: &str = "0xcEe38fB7D7c6ed6BABc18898BDEF67ED572Cc9D0";
-const MOO_SETTLEMENT_CONTRACT_ADDRESS: &str = "0x6d64978ec6Dc0b0175897F1b3F13BB9E6396C7e3";
+pub const MOO_SETTLEMENT_CONTRACT_ADDRESS: &str = "0x6d64978ec6Dc0b0175897F1b3F13BB9E6396C7e3";
+/// `GPv2Settlement`, which executes the interactions of a solution and
+/// receives what AMMs pay out.
+const SETTLEMENT_CONTRACT_ADDRESS: &str = "0x9008D19f58AAbD9eD0D60971565AA8510560ab41";
+/// Stands in for native ETH as the token of orders.
+const NATIVE_TOKEN_ADDRESS: &str = "0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE";
+
+/// How the solver is set up for the chain it settles on.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub slippage: SlippagePolicy,
+    pub risk_limits: RiskLimits,
+    /// `WETH9`, which native ETH gets wrapped into and unwrapped from.
+    pub weth: H160,
+}
+
+/// The canonical `WETH9` deployment of the chains we know it for.
+pub fn weth_address(chain_id: u64) -> Option<H160> {
+    let address = match chain_id {
+        1 => "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        5 => "0xB4FBF271143F4FBf7B91A5ded31805e42b2208d6",
+        100 => "0xe91D153E0b41518A2Ce8Dd3D7944Fa863463a97d",
+        11155111 => "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14",
+        _ => return None,
+    };
+    Some(H160::from_str(address).unwrap())
+}
+
+/// How long we take for auctions that don't set a `time_limit`.
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(15);
+/// Kept in reserve from the time limit to finish building and sending back a
+/// solution after work has been cut short.
+const DEADLINE_MARGIN: Duration = Duration::from_millis(500);
+
+/// Estimated gas of settling a maker order through `MooSettlementContract`.
+const MAKER_ORDER_GAS: u64 = 110_000;
+/// Estimated gas of transferring into a Uniswap V2 like pair and swapping on
+/// it.
+const UNISWAP_V2_SWAP_GAS: u64 = 90_000;
+
+/// Where a candidate solution gets the liquidity for user orders that can't
+/// be matched against each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    Makers,
+    Amms,
+}
+
+/// How to build a candidate solution: whether to match user orders against
+/// each other first, and the sources to fill the rest from, in the order they
+/// are tried for every order.
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    cow: bool,
+    sources: &'static [Source],
+}
+
+/// Every way of combining matching user orders against each other with the
+/// liquidity sources, in the order the naive strategy tries them.
+const CANDIDATES: &[Candidate] = &[
+    Candidate {
+        cow: true,
+        sources: &[Source::Makers, Source::Amms],
+    },
+    Candidate {
+        cow: true,
+        sources: &[Source::Amms, Source::Makers],
+    },
+    Candidate {
+        cow: true,
+        sources: &[Source::Makers],
+    },
+    Candidate {
+        cow: true,
+        sources: &[Source::Amms],
+    },
+    Candidate {
+        cow: false,
+        sources: &[Source::Makers, Source::Amms],
+    },
+    Candidate {
+        cow: false,
+        sources: &[Source::Amms, Source::Makers],
+    },
+    Candidate {
+        cow: false,
+        sources: &[Source::Makers],
+    },
+    Candidate {
+        cow: false,
+        sources: &[Source::Amms],
+    },
+];
 
 pub async fn solve(
-    BatchAuctionModel { orders, tokens, .. }: BatchAuctionModel,
+    model: BatchAuctionModel,
     web3: Web3<Http>,
+    order_book: &OrderBook,
+    fill_ledger: &FillLedger,
+    config: &Config,
+    strategies: &[Box<dyn SolverStrategy>],
 ) -> Result<SettledBatchAuctionModel> {
-    let token_in = H160::from_str("0x6778ac35e1c9aca22a8d7d820577212a89544df9").unwrap();
-    let token_out = H160::from_str("0xb4fbf271143f4fbf7b91a5ded31805e42b2208d6").unwrap();
-    println!("check");
-    if let Some((index, order_model)) = orders
+    let solved = match candidates(&model, web3, order_book, fill_ledger, config, strategies)
+        .await
+        .into_iter()
+        .next()
+    {
+        Some(solved) => solved,
+        None => return Ok(SettledBatchAuctionModel::default()),
+    };
+    propose(fill_ledger, model.auction_id, &solved);
+    Ok(solved.settlement)
+}
+
+/// Every distinct solution worth settling along with its objective value,
+/// best first, so that the driver can fall back to the next one when the
+/// first fails simulation. The maker orders of all of them count as
+/// proposed, since the driver may submit any one.
+pub async fn solve_ranked(
+    model: BatchAuctionModel,
+    web3: Web3<Http>,
+    order_book: &OrderBook,
+    fill_ledger: &FillLedger,
+    config: &Config,
+    strategies: &[Box<dyn SolverStrategy>],
+) -> Result<Vec<RankedSolutionModel>> {
+    let ranked = candidates(&model, web3, order_book, fill_ledger, config, strategies).await;
+    for solved in &ranked {
+        propose(fill_ledger, model.auction_id, solved);
+    }
+    Ok(ranked
         .into_iter()
-        .find(|(_, order)| order.sell_token == token_in && order.buy_token == token_out)
+        .map(|solved| RankedSolutionModel {
+            objective_value: solved.objective_value,
+            solution: solved.settlement,
+        })
+        .collect())
+}
+
+/// Sets up the chain context for `model` and ranks the candidates the
+/// strategies build in it.
+async fn candidates(
+    model: &BatchAuctionModel,
+    web3: Web3<Http>,
+    order_book: &OrderBook,
+    fill_ledger: &FillLedger,
+    config: &Config,
+    strategies: &[Box<dyn SolverStrategy>],
+) -> Vec<CandidateSolution> {
+    let time_limit = model
+        .time_limit
+        .map_or(DEFAULT_TIME_LIMIT, Duration::from_secs);
+    let deadline = Instant::now() + time_limit.saturating_sub(DEADLINE_MARGIN);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    fill_ledger.expire(now);
+    let expired = order_book.remove_expired(now);
+    if !expired.is_empty() {
+        tracing::debug!(count = expired.len(), "removed expired maker orders");
+    }
+
+    let gas_price = match model
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.gas_price)
     {
-        if ALREADY_EXECUTED.swap(true, Ordering::Relaxed) {
-            return Ok(Default::default());
-        }
-        let amount_in = U256::from_str_radix("100000000000000000", 10).unwrap();
-        let amount_out = U256::from_str_radix("1000000000000000000", 10).unwrap();
-
-        let ref_token = get_ref_token(&tokens).unwrap();
-        println!("in");
-
-        let executed_order = ExecutedOrderModel {
-            exec_sell_amount: amount_in,
-            exec_buy_amount: amount_out,
-            exec_fee_amount: order_model.allow_partial_fill.then_some(100.into()),
-        };
-        let mut calculated_prices = HashMap::new();
-        let decimals = tokens.get(&ref_token).unwrap().decimals.unwrap_or(18);
-        let ref_token_price = U256::exp10(decimals as usize);
-        calculated_prices.insert(ref_token, ref_token_price);
-        calculate_prices_for_order(&order_model, amount_in, amount_out, &mut calculated_prices)?;
-
-        let contract = MooSettlementContract::at(
-            &web3,
-            H160::from_str(MOO_SETTLEMENT_CONTRACT_ADDRESS).unwrap(),
-        );
-
-        let settlement_contract_order = Order {
-            token_in,
-            amount_in,
-            token_out,
-            amount_out,
-            valid_to: U256::from(1747179217),
-            maker: H160::from_str("d1f5c19d7330F333F28A5CF3F391Bf679aC55841").unwrap(),
-            uid: Bytes([1].to_vec()),
-        };
-
-        let signature = Vec::from_hex("3bdf1180a463da09ffc18d45937e66767cb76044b85a6108751796c8ab8a01130f5c5dad1d5282a1f5880bbc6ddb8fe194e427523a5ea32ddf1d858a23113ffd1c").unwrap();
-        let interaction = MooSettlementInteraction {
-            order: settlement_contract_order,
-            signature: signature.into(),
-            moo: contract,
-        }
-        .encode();
-
-        let encoded_interaction = interaction.first().unwrap();
-
-        let approval = ApprovalModel {
-            token: token_in,
-            spender: encoded_interaction.target,
-            amount: amount_in,
-        };
-        let interaction_data = InteractionData {
-            target: encoded_interaction.target,
-            value: encoded_interaction.value,
-            call_data: encoded_interaction.call_data.0.clone(),
-            exec_plan: Default::default(),
-            inputs: vec![TokenAmount {
-                amount: amount_in,
-                token: order_model.sell_token,
-            }],
-            outputs: vec![TokenAmount {
-                amount: amount_out,
-                token: order_model.buy_token,
-            }],
-        };
-
-        Ok(SettledBatchAuctionModel {
-            orders: HashMap::from([(index, executed_order)]),
-            amms: Default::default(),
-            ref_token: Some(ref_token),
-            prices: calculated_prices,
-            approvals: vec![approval],
-            interaction_data: vec![interaction_data],
+        Some(gas_price) => Some(gas_price),
+        None => current_gas_price(&web3, deadline).await,
+    };
+    let contract = MooSettlementContract::at(
+        &web3,
+        H160::from_str(MOO_SETTLEMENT_CONTRACT_ADDRESS).unwrap(),
+    );
+    let maker_orders = usable_maker_orders(
+        &model.orders,
+        order_book,
+        fill_ledger,
+        &contract,
+        config.weth,
+        now + time_limit.as_secs(),
+        deadline,
+    )
+    .await;
+    let maker_orders = maker_orders.iter().collect::<Vec<_>>();
+    let context = ChainContext {
+        web3: &web3,
+        contract: &contract,
+        maker_orders: &maker_orders,
+        gas_price,
+        slippage: config.slippage,
+        risk_limits: config.risk_limits,
+        weth: config.weth,
+        deadline,
+    };
+    ranked_solutions(strategies, model, &context)
+}
+
+/// The driver may still execute internal interactions, so their maker orders
+/// count as proposed all the same. The order book only takes orders with
+/// well-formed uids.
+fn propose(fill_ledger: &FillLedger, auction_id: Option<u64>, solved: &CandidateSolution) {
+    for maker_order in &solved.maker_orders {
+        if let Ok(uid) = maker_order.order.parse_uid() {
+            fill_ledger.propose(auction_id, uid);
+        }
+    }
+}
+
+/// Runs every strategy and ranks the distinct candidates by objective value,
+/// highest first and in the order they were built on ties. Candidates with a
+/// negative objective value aren't worth settling, invalid ones are dropped
+/// before they get to the driver. Once the deadline has passed, no more
+/// strategies are run after the first candidate was found.
+fn ranked_solutions(
+    strategies: &[Box<dyn SolverStrategy>],
+    model: &BatchAuctionModel,
+    context: &ChainContext,
+) -> Vec<CandidateSolution> {
+    let mut ranked = Vec::<CandidateSolution>::new();
+    // Different candidates often end up with the same settlement, which the
+    // driver has no use for twice.
+    let mut settlements = Vec::new();
+    for strategy in strategies {
+        if !ranked.is_empty() && Instant::now() >= context.deadline {
+            tracing::debug!("deadline reached, skipping remaining strategies");
+            break;
+        }
+        for solved in strategy.solve(model, context) {
+            tracing::debug!(
+                strategy = strategy.name(),
+                objective_value = solved.objective_value,
+                "built candidate"
+            );
+            if solved.objective_value < 0. {
+                continue;
+            }
+            if let Err(err) = validate::validate(model, &solved.settlement) {
+                tracing::warn!(
+                    strategy = strategy.name(),
+                    ?err,
+                    settlement = ?solved.settlement,
+                    "dropping invalid candidate"
+                );
+                continue;
+            }
+            let settlement = serde_json::to_value(&solved.settlement).ok();
+            if settlement.is_some() && settlements.contains(&settlement) {
+                continue;
+            }
+            settlements.push(settlement);
+            ranked.push(solved);
+        }
+    }
+    ranked.sort_by(|a, b| b.objective_value.total_cmp(&a.objective_value));
+    ranked
+}
+
+/// The parts of a `BatchAuctionModel` solutions get built from.
+#[derive(Debug)]
+struct Auction {
+    orders: BTreeMap<usize, OrderModel>,
+    tokens: BTreeMap<H160, TokenInfoModel>,
+    amms: BTreeMap<usize, AmmModel>,
+    ref_token: H160,
+    ref_token_price: U256,
+    /// How many orders a solution may execute at most.
+    max_nr_exec_orders: Option<usize>,
+    /// The gas price in wei partially fillable orders pay for the gas of
+    /// their interactions at.
+    gas_price: Option<f64>,
+    /// What AMM swaps leave room for in case reserves change until they are
+    /// executed.
+    slippage: SlippagePolicy,
+    /// How much the maker orders of a solution may add up to.
+    risk_limits: RiskLimits,
+    weth: H160,
+    /// The orders buying native ETH, which buy WETH in `orders` instead.
+    buys_native: HashSet<usize>,
+}
+
+impl Auction {
+    /// `None` for auctions without any tokens to normalize prices by.
+    fn new(model: &BatchAuctionModel, context: &ChainContext) -> Option<Self> {
+        let ref_token = get_ref_token(&model.tokens)?;
+        // The settlement contract never receives the native ETH such orders
+        // sell, so there is nothing to wrap into WETH for them.
+        let mut orders = model
+            .orders
+            .iter()
+            .filter(|(_, order)| order.sell_token != native_token())
+            .map(|(index, order)| (*index, order.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let mut buys_native = HashSet::new();
+        for (index, order) in &mut orders {
+            if order.buy_token == native_token() {
+                order.buy_token = context.weth;
+                buys_native.insert(*index);
+            }
+        }
+        let mut tokens = model.tokens.clone();
+        if let Some(info) = tokens.get(&native_token()).cloned() {
+            tokens.entry(context.weth).or_insert(info);
+        }
+        Some(Self {
+            orders,
+            tokens,
+            amms: model.amms.clone(),
+            ref_token,
+            ref_token_price: one_unit(model.tokens.get(&ref_token)),
+            max_nr_exec_orders: model.max_nr_exec_orders.map(|max| max as usize),
+            gas_price: context.gas_price,
+            slippage: context.slippage,
+            risk_limits: context.risk_limits,
+            weth: context.weth,
+            buys_native,
+        })
+    }
+
+    /// The token to normalize the prices of a `group` of connected tokens by,
+    /// and its price. That is the reference token of the auction if the group
+    /// trades it, otherwise the group's own reference token, priced relative
+    /// to the auction's by their external prices where it can be.
+    fn reference(&self, group: &BTreeSet<H160>) -> Option<(H160, U256)> {
+        if group.contains(&self.ref_token) {
+            return Some((self.ref_token, self.ref_token_price));
+        }
+        let token = get_ref_token(
+            group
+                .iter()
+                .filter_map(|token| Some((token, self.tokens.get(token)?))),
+        )
+        .or_else(|| group.first().copied())?;
+        let external_price = |token| {
+            self.tokens
+                .get(&token)
+                .and_then(|info| info.external_price)
+                .filter(|price| *price > 0.)
+        };
+        let price = external_price(self.ref_token)
+            .zip(external_price(token))
+            .and_then(|(ref_price, price)| {
+                objective::from_f64(
+                    (objective::to_f64(self.ref_token_price) * price / ref_price).round(),
+                )
+            })
+            .filter(|price| !price.is_zero())
+            .unwrap_or_else(|| one_unit(self.tokens.get(&token)));
+        Some((token, price))
+    }
+}
+
+/// The executed orders, the maker orders and AMM swaps filling them and the
+/// uniform clearing prices of a solution.
+#[derive(Debug)]
+struct Solution<'a> {
+    orders: HashMap<usize, ExecutedOrderModel>,
+    maker_orders: Vec<&'a SignedOrder>,
+    swaps: Vec<amm::Swap>,
+    prices: HashMap<H160, U256>,
+    /// The maker orders and the pools of the swaps whose interactions the
+    /// buffers of the settlement contract cover.
+    internal_quotes: HashSet<QuoteId<'a>>,
+    internal_pools: HashSet<usize>,
+}
+
+/// Builds a solution for every candidate, stopping after the first one that
+/// executes any orders once the deadline has passed.
+fn candidate_solutions(
+    auction: &Auction,
+    candidates: &[Candidate],
+    context: &ChainContext,
+) -> Vec<CandidateSolution> {
+    let quotes = context
+        .maker_orders
+        .iter()
+        .copied()
+        .map(Quote::Maker)
+        .chain(liquidity_orders(&auction.orders))
+        .collect::<Vec<_>>();
+    let pools = auction
+        .amms
+        .iter()
+        .filter_map(|(id, amm)| amm::Pool::from_model(*id, amm))
+        .collect::<Vec<_>>();
+    let mut solutions = Vec::new();
+    for candidate in candidates {
+        if !solutions.is_empty() && Instant::now() >= context.deadline {
+            tracing::debug!("deadline reached, skipping remaining candidates");
+            break;
+        }
+        let solved = match solve_orders(auction, &quotes, &pools, candidate) {
+            Ok(solved) => solved,
+            Err(err) => {
+                tracing::debug!(?err, ?candidate, "failed to build candidate");
+                continue;
+            }
+        };
+        if solved.orders.is_empty() {
+            continue;
+        }
+        solutions.push(CandidateSolution {
+            objective_value: objective::objective_value(auction, &solved),
+            maker_orders: solved.maker_orders.iter().copied().cloned().collect(),
+            settlement: settle(auction, solved, context),
+        });
+    }
+    solutions
+}
+
+/// The solution as sent to the driver, with the interactions of its maker
+/// orders and swaps. The settlement contract unwraps the native ETH orders
+/// buy after all of them.
+fn settle(auction: &Auction, solved: Solution, context: &ChainContext) -> SettledBatchAuctionModel {
+    let bought = auction
+        .buys_native
+        .iter()
+        .filter_map(|index| solved.orders.get(index))
+        .fold(U256::zero(), |total, executed| {
+            total.saturating_add(executed.exec_buy_amount)
+        });
+    let mut prices = solved.prices;
+    if let Some(price) = prices.get(&auction.weth).copied() {
+        if !bought.is_zero() {
+            prices.insert(native_token(), price);
+        }
+    }
+
+    let mut solution = SettledBatchAuctionModel {
+        orders: solved.orders,
+        ref_token: Some(auction.ref_token),
+        prices,
+        ..Default::default()
+    };
+    for maker_order in solved.maker_orders {
+        let internal = solved
+            .internal_quotes
+            .contains(&QuoteId::Maker(&maker_order.order.uid));
+        add_maker_order(&mut solution, maker_order, context.contract, internal);
+    }
+    for swap in &solved.swaps {
+        let pool = amm::Pool::from_model(swap.pool, &auction.amms[&swap.pool]).unwrap();
+        let internal = solved.internal_pools.contains(&swap.pool);
+        add_swap(&mut solution, &pool, swap, context.web3, internal);
+    }
+    if !bought.is_zero() {
+        unwrap_weth(&mut solution, auction.weth, bought, context.web3);
+    }
+    solution
+}
+
+fn solve_orders<'a>(
+    auction: &Auction,
+    quotes: &[Quote<'a>],
+    pools: &[amm::Pool],
+    candidate: &Candidate,
+) -> Result<Solution<'a>> {
+    let orders = &auction.orders;
+    let mut matches = match_orders(auction, quotes, pools, candidate);
+    let prices = loop {
+        let prices =
+            prices::clearing_prices(&trades(orders, &matches), |group| auction.reference(group))?;
+        // Drop matches that don't work out at the uniform prices one by one,
+        // since every match we drop can change the prices of the others.
+        if let Some(position) = matches
+            .iter()
+            .position(|order_match| !clears_at(auction, order_match, &prices))
+        {
+            tracing::debug!(orders = ?matches[position].orders, "dropping match");
+            matches.remove(position);
+            continue;
+        }
+
+        let nr_orders = matches.iter().map(Match::nr_orders).sum::<usize>();
+        match auction.max_nr_exec_orders {
+            Some(max) if nr_orders > max => {
+                let values = matches
+                    .iter()
+                    .map(|order_match| {
+                        Ok((
+                            order_match.nr_orders(),
+                            objective::match_value(auction, order_match, &prices)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mut keep = most_valuable(&values, max).into_iter();
+                matches.retain(|order_match| {
+                    let keep = keep.next().unwrap();
+                    if !keep {
+                        tracing::debug!(orders = ?order_match.orders, "dropping match over the order limit");
+                    }
+                    keep
+                });
+            }
+            _ => break prices,
+        }
+    };
+    internalize(auction, &mut matches);
+
+    let mut solution = Solution {
+        orders: HashMap::new(),
+        maker_orders: Vec::new(),
+        swaps: Vec::new(),
+        prices: HashMap::new(),
+        internal_quotes: HashSet::new(),
+        internal_pools: HashSet::new(),
+    };
+    if matches.is_empty() {
+        return Ok(solution);
+    }
+    for order_match in matches {
+        solution
+            .orders
+            .extend(executed_orders(auction, &order_match, &prices)?);
+        solution
+            .maker_orders
+            .extend(order_match.quotes.iter().filter_map(|quote| match quote {
+                Quote::Maker(maker_order) => Some(*maker_order),
+                Quote::Liquidity(..) => None,
+            }));
+        solution.swaps.extend(order_match.swaps);
+        solution.internal_quotes.extend(order_match.internal_quotes);
+        solution.internal_pools.extend(order_match.internal_pools);
+    }
+    solution.prices = prices;
+    Ok(solution)
+}
+
+/// Marks the maker orders and swaps of `matches` as internal for as long as
+/// the buffers of the settlement contract cover what they pay out, so that
+/// their interactions don't need to be executed on chain.
+fn internalize(auction: &Auction, matches: &mut [Match]) {
+    let mut buffers = auction
+        .tokens
+        .iter()
+        .filter_map(|(token, info)| Some((*token, info.internal_buffer?)))
+        .collect::<HashMap<_, _>>();
+    let mut covers = |token: H160, amount: U256| match buffers.get_mut(&token) {
+        Some(buffer) if *buffer >= amount => {
+            *buffer -= amount;
+            true
+        }
+        _ => false,
+    };
+    for order_match in matches {
+        for quote in &order_match.quotes {
+            if matches!(quote, Quote::Maker(_)) && covers(quote.token_out(), quote.amount_out()) {
+                order_match.internal_quotes.insert(quote.id());
+            }
+        }
+        for swap in &order_match.swaps {
+            if covers(swap.token_out, swap.amount_out) {
+                order_match.internal_pools.insert(swap.pool);
+            }
+        }
+    }
+}
+
+/// Which of the matches, given as the number of orders they execute and
+/// their value, to keep for the highest total value with at most `max`
+/// orders executed.
+fn most_valuable(matches: &[(usize, f64)], max: usize) -> Vec<bool> {
+    // `best[i][n]` is the highest value of the first `i` matches executing at
+    // most `n` orders.
+    let mut best = vec![vec![0.; max + 1]; matches.len() + 1];
+    for (i, (nr_orders, value)) in matches.iter().enumerate() {
+        for n in 0..=max {
+            best[i + 1][n] = best[i][n];
+            if *nr_orders <= n && best[i][n - nr_orders] + value > best[i + 1][n] {
+                best[i + 1][n] = best[i][n - nr_orders] + value;
+            }
+        }
+    }
+    let mut keep = vec![false; matches.len()];
+    let mut n = max;
+    for i in (0..matches.len()).rev() {
+        if best[i + 1][n] != best[i][n] {
+            keep[i] = true;
+            n -= matches[i].0;
+        }
+    }
+    keep
+}
+
+/// The user orders of `order_match` executed at the uniform clearing `prices`,
+/// and its liquidity orders executed at exactly their limit price without
+/// paying a fee.
+fn executed_orders(
+    auction: &Auction,
+    order_match: &Match,
+    prices: &HashMap<H160, U256>,
+) -> Result<Vec<(usize, ExecutedOrderModel)>> {
+    let mut executed_orders = order_match
+        .orders
+        .iter()
+        .map(|(index, sell_amount, buy_amount)| {
+            let executed = executed_order(
+                auction,
+                &auction.orders[index],
+                *sell_amount,
+                *buy_amount,
+                order_match.gas_per_order(),
+                prices,
+            )?;
+            Ok((*index, executed))
         })
+        .collect::<Result<Vec<_>>>()?;
+    let liquidity_orders = order_match.quotes.iter().filter_map(|quote| match quote {
+        Quote::Liquidity(index, order) => Some((
+            *index,
+            ExecutedOrderModel {
+                exec_sell_amount: order.sell_amount,
+                exec_buy_amount: order.buy_amount,
+                exec_fee_amount: None,
+            },
+        )),
+        Quote::Maker(_) => None,
+    });
+    executed_orders.extend(liquidity_orders);
+    Ok(executed_orders)
+}
+
+/// User orders that get settled together, along with the quotes and AMM swaps
+/// filling them. Either all of them make it into the solution or none does.
+#[derive(Debug)]
+struct Match<'a> {
+    /// Indices of the user orders and the sell and buy amounts they were
+    /// matched at.
+    orders: Vec<(usize, U256, U256)>,
+    quotes: Vec<Quote<'a>>,
+    swaps: Vec<amm::Swap>,
+    /// The maker orders and the pools of the swaps that are settled from the
+    /// buffers of the settlement contract.
+    internal_quotes: HashSet<QuoteId<'a>>,
+    internal_pools: HashSet<usize>,
+}
+
+impl Match<'_> {
+    /// How many orders of the auction the match executes, liquidity orders
+    /// included.
+    fn nr_orders(&self) -> usize {
+        self.orders.len()
+            + self
+                .quotes
+                .iter()
+                .filter(|quote| matches!(quote, Quote::Liquidity(..)))
+                .count()
+    }
+
+    /// The estimated gas of the interactions settling the match, split evenly
+    /// between its user orders. Internal interactions don't use any.
+    fn gas_per_order(&self) -> u64 {
+        let makers = self
+            .quotes
+            .iter()
+            .filter(|quote| {
+                matches!(quote, Quote::Maker(_)) && !self.internal_quotes.contains(&quote.id())
+            })
+            .count() as u64;
+        let swaps = self
+            .swaps
+            .iter()
+            .filter(|swap| !self.internal_pools.contains(&swap.pool))
+            .count() as u64;
+        let gas = makers * MAKER_ORDER_GAS + swaps * UNISWAP_V2_SWAP_GAS;
+        gas.div_ceil(self.orders.len().max(1) as u64)
+    }
+}
+
+/// Matches user orders against each other first if the candidate does, so
+/// that quotes only need to fill what is left over, and then the remaining
+/// orders against the candidate's sources. Every quote and pool is used at
+/// most once, and maker orders only within the risk limits. Liquidity orders
+/// only ever serve as quotes.
+fn match_orders<'a>(
+    auction: &Auction,
+    quotes: &[Quote<'a>],
+    pools: &[amm::Pool],
+    candidate: &Candidate,
+) -> Vec<Match<'a>> {
+    let orders = &auction.orders;
+    let mut matches = Vec::new();
+    let mut matched_orders = HashSet::new();
+    let mut used_quotes = HashSet::new();
+    let mut used_pools = HashSet::new();
+    let mut exposure = Exposure::default();
+    let quotes = if candidate.sources.contains(&Source::Makers) {
+        quotes
     } else {
-        Ok(SettledBatchAuctionModel::default())
+        &[]
+    };
+    let user_orders = || orders.iter().filter(|(_, order)| !order.is_liquidity_order);
+
+    for (first_index, first) in user_orders().filter(|_| candidate.cow) {
+        if matched_orders.contains(first_index) {
+            continue;
+        }
+        for (second_index, second) in user_orders().filter(|(index, _)| *index > first_index) {
+            if matched_orders.contains(second_index) || !cow::is_opposite(first, second) {
+                continue;
+            }
+            let residual_quotes = admitted(auction, &exposure, unused(quotes, &used_quotes));
+            let cow_match = match cow::match_orders(first, second, &residual_quotes) {
+                Some(cow_match) => cow_match,
+                None => continue,
+            };
+            matched_orders.extend([*first_index, *second_index]);
+            let [(first_sell, first_buy), (second_sell, second_buy)] = cow_match.executed;
+            matches.push(Match {
+                orders: vec![
+                    (*first_index, first_sell, first_buy),
+                    (*second_index, second_sell, second_buy),
+                ],
+                quotes: cow_match.residual.into_iter().collect(),
+                swaps: Vec::new(),
+                internal_quotes: HashSet::new(),
+                internal_pools: HashSet::new(),
+            });
+            if let Some(quote) = cow_match.residual {
+                used_quotes.insert(quote.id());
+                exposure.try_add(&auction.risk_limits, &auction.tokens, &[quote]);
+            }
+            break;
+        }
+    }
+
+    for (index, order) in user_orders() {
+        if matched_orders.contains(index) {
+            continue;
+        }
+        let order_match = candidate.sources.iter().find_map(|source| match source {
+            Source::Makers => {
+                let fill = fill_from_quotes(
+                    order,
+                    &admitted(auction, &exposure, unused(quotes, &used_quotes)),
+                )?;
+                // Each quote fits on its own, but all of them together may
+                // not.
+                if !exposure.try_add(&auction.risk_limits, &auction.tokens, &fill) {
+                    return None;
+                }
+                let (sell_amount, buy_amount) = fill.iter().fold(
+                    (U256::zero(), U256::zero()),
+                    |(sell_amount, buy_amount), quote| {
+                        (
+                            sell_amount + quote.amount_in(),
+                            buy_amount.saturating_add(quote.amount_out()),
+                        )
+                    },
+                );
+                Some(Match {
+                    orders: vec![(*index, sell_amount, buy_amount)],
+                    quotes: fill,
+                    swaps: Vec::new(),
+                    internal_quotes: HashSet::new(),
+                    internal_pools: HashSet::new(),
+                })
+            }
+            Source::Amms => {
+                let unused_pools = pools
+                    .iter()
+                    .filter(|pool| !used_pools.contains(&pool.id))
+                    .collect::<Vec<_>>();
+                let swap = with_slippage(auction, order, amm::route(order, &unused_pools)?)?;
+                Some(Match {
+                    orders: vec![(*index, swap.amount_in, swap.amount_out)],
+                    quotes: Vec::new(),
+                    swaps: vec![swap],
+                    internal_quotes: HashSet::new(),
+                    internal_pools: HashSet::new(),
+                })
+            }
+        });
+        if let Some(order_match) = order_match {
+            used_quotes.extend(order_match.quotes.iter().map(Quote::id));
+            used_pools.extend(order_match.swaps.iter().map(|swap| swap.pool));
+            matches.push(order_match);
+        }
     }
+    matches
 }
 
-fn get_ref_token(tokens: &BTreeMap<H160, TokenInfoModel>) -> Option<H160> {
-    tokens
+/// Leaves room for the reserves of the pool to change until `swap` gets
+/// executed: sell orders accept less out of it and buy orders pay more into
+/// it, as long as that is still at least their limit price.
+fn with_slippage(auction: &Auction, order: &OrderModel, mut swap: amm::Swap) -> Option<amm::Swap> {
+    let external_price = |token| auction.tokens.get(&token)?.external_price;
+    if order.is_sell_order {
+        let tolerance = auction
+            .slippage
+            .tolerance(swap.amount_out, external_price(swap.token_out));
+        swap.amount_out = swap.amount_out.checked_sub(tolerance)?;
+    } else {
+        let tolerance = auction
+            .slippage
+            .tolerance(swap.amount_in, external_price(swap.token_in));
+        swap.amount_in = swap.amount_in.checked_add(tolerance)?;
+    }
+    (!swap.amount_out.is_zero() && satisfies_limit_price(order, swap.amount_in, swap.amount_out))
+        .then_some(swap)
+}
+
+fn trades(orders: &BTreeMap<usize, OrderModel>, matches: &[Match]) -> Vec<prices::Trade> {
+    matches
+        .iter()
+        .flat_map(|order_match| &order_match.orders)
+        .map(|(index, sell_amount, buy_amount)| prices::Trade {
+            sell_token: orders[index].sell_token,
+            buy_token: orders[index].buy_token,
+            sell_amount: *sell_amount,
+            buy_amount: *buy_amount,
+        })
+        .collect()
+}
+
+/// Whether every order in `order_match` is priced and gets at least its limit
+/// price at the uniform clearing `prices`, counting the fee partially
+/// fillable orders pay for their gas as sold, and fill-or-kill orders are
+/// filled completely.
+fn clears_at(auction: &Auction, order_match: &Match, prices: &HashMap<H160, U256>) -> bool {
+    let gas = order_match.gas_per_order();
+    order_match
+        .orders
         .iter()
-        .fold(None, |result: Option<(&H160, &TokenInfoModel)>, current| {
-            if let Some(result) = result {
-                if current.1.normalize_priority > result.1.normalize_priority {
-                    Some(current)
-                } else {
-                    Some(result)
+        .all(|(index, sell_amount, buy_amount)| {
+            let order = &auction.orders[index];
+            executed_order(auction, order, *sell_amount, *buy_amount, gas, prices).is_ok_and(
+                |executed| {
+                    let (sell_amount, buy_amount) =
+                        (executed.exec_sell_amount, executed.exec_buy_amount);
+                    let fee = if order.allow_partial_fill {
+                        gas_fee(auction, order, gas).unwrap_or_default()
+                    } else {
+                        U256::zero()
+                    };
+                    let executed_amount = executed_amount(order, sell_amount, buy_amount);
+                    !executed_amount.is_zero()
+                        && (order.allow_partial_fill || executed_amount == fixed_amount(order))
+                        && sell_amount.checked_add(fee).is_some_and(|sell_amount| {
+                            satisfies_limit_price(order, sell_amount, buy_amount)
+                        })
+                },
+            )
+        })
+}
+
+/// The amount of `order` that is fixed when executing it: what sell orders
+/// sell and what buy orders buy. The other side follows from the price.
+fn fixed_amount(order: &OrderModel) -> U256 {
+    executed_amount(order, order.sell_amount, order.buy_amount)
+}
+
+fn executed_amount(order: &OrderModel, sell_amount: U256, buy_amount: U256) -> U256 {
+    if order.is_sell_order {
+        sell_amount
+    } else {
+        buy_amount
+    }
+}
+
+/// Executes `fixed` of `order` at `(sell_amount, buy_amount)`. Whatever gets
+/// rounded is rounded in favour of the user, the way the settlement contract
+/// does it: sell orders receive more, buy orders pay less.
+fn execute(
+    order: &OrderModel,
+    fixed: U256,
+    (sell_amount, buy_amount): (U256, U256),
+) -> Result<(U256, U256), arithmetic::ArithmeticError> {
+    if order.is_sell_order {
+        Ok((
+            fixed,
+            mul_div(fixed, buy_amount, sell_amount, Rounding::Up)?,
+        ))
+    } else {
+        Ok((
+            mul_div(fixed, sell_amount, buy_amount, Rounding::Down)?,
+            fixed,
+        ))
+    }
+}
+
+/// Re-executes an order that was matched at `sell_amount` and `buy_amount`
+/// for the same fixed amount at the uniform clearing `prices`.
+fn execute_at(
+    order: &OrderModel,
+    sell_amount: U256,
+    buy_amount: U256,
+    prices: &HashMap<H160, U256>,
+) -> Result<(U256, U256)> {
+    let rate = prices::exchange_rate(prices, order.sell_token, order.buy_token)
+        .ok_or_else(|| anyhow!("order trades an unpriced token"))?;
+    Ok(execute(
+        order,
+        executed_amount(order, sell_amount, buy_amount),
+        rate,
+    )?)
+}
+
+/// Collects the maker orders on pairs traded by `orders` that are still valid
+/// when a settlement is due at the unix timestamp `settled_by` and haven't
+/// been consumed already, as far as that can be checked before `deadline`.
+async fn usable_maker_orders(
+    orders: &BTreeMap<usize, OrderModel>,
+    order_book: &OrderBook,
+    fill_ledger: &FillLedger,
+    contract: &MooSettlementContract,
+    weth: H160,
+    settled_by: u64,
+    deadline: Instant,
+) -> Vec<SignedOrder> {
+    let pairs = orders
+        .values()
+        .flat_map(|order| {
+            let (sell_token, buy_token) =
+                (erc20(order.sell_token, weth), erc20(order.buy_token, weth));
+            [(sell_token, buy_token), (buy_token, sell_token)]
+        })
+        .collect::<HashSet<_>>();
+    let candidates = pairs
+        .into_iter()
+        .flat_map(|(token_in, token_out)| {
+            order_book.orders_for_pair(token_in, token_out, settled_by)
+        })
+        .filter(|maker_order| {
+            maker_order
+                .order
+                .parse_uid()
+                .is_ok_and(|uid| fill_ledger.is_available(&uid))
+        })
+        .collect::<Vec<_>>();
+
+    // Checks still outstanding at the deadline are dropped, which cancels
+    // their RPC calls, and their maker orders aren't used in this auction.
+    let mut checks = candidates
+        .into_iter()
+        .map(|maker_order| async move {
+            let order_hash = signature::order_hash(order_book.domain(), &maker_order.order);
+            let consumed = is_consumed_on_chain(contract, order_hash).await;
+            (maker_order, consumed)
+        })
+        .collect::<FuturesUnordered<_>>();
+    let mut usable = Vec::new();
+    let checked = tokio::time::timeout_at(deadline, async {
+        while let Some((maker_order, consumed)) = checks.next().await {
+            match consumed {
+                Ok(false) => usable.push(maker_order),
+                Ok(true) => {
+                    if let Ok(uid) = maker_order.order.parse_uid() {
+                        fill_ledger.settle(uid);
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(?err, uid = ?maker_order.order.uid, "failed to check maker order")
                 }
-            } else {
-                Some(current)
             }
+        }
+    })
+    .await;
+    if checked.is_err() {
+        tracing::warn!(
+            unchecked = checks.len(),
+            "deadline reached while checking maker orders"
+        );
+    }
+    usable
+}
+
+/// The liquidity orders of the auction as quotes for user orders.
+fn liquidity_orders(orders: &BTreeMap<usize, OrderModel>) -> impl Iterator<Item = Quote<'_>> {
+    orders
+        .iter()
+        .filter(|(_, order)| {
+            order.is_liquidity_order && !order.sell_amount.is_zero() && !order.buy_amount.is_zero()
+        })
+        .map(|(index, order)| Quote::Liquidity(*index, order))
+}
+
+fn unused<'a>(quotes: &[Quote<'a>], used: &HashSet<QuoteId>) -> Vec<Quote<'a>> {
+    quotes
+        .iter()
+        .filter(|quote| !used.contains(&quote.id()))
+        .copied()
+        .collect()
+}
+
+/// The `quotes` that fit into the risk limits on top of `exposure`.
+fn admitted<'a>(auction: &Auction, exposure: &Exposure, quotes: Vec<Quote<'a>>) -> Vec<Quote<'a>> {
+    quotes
+        .into_iter()
+        .filter(|quote| exposure.admits(&auction.risk_limits, &auction.tokens, &[*quote]))
+        .collect()
+}
+
+/// Picks the quotes to fill `order` with. Quotes are taken best exchange rate
+/// first for as long as they fit into what `order` sells, or buys for buy
+/// orders, each of them at least at the order's limit price. Fill-or-kill
+/// orders need quotes adding up to exactly that amount.
+fn fill_from_quotes<'a>(order: &OrderModel, quotes: &[Quote<'a>]) -> Option<Vec<Quote<'a>>> {
+    let mut candidates = quotes
+        .iter()
+        .filter(|quote| {
+            quote.token_in() == order.sell_token && quote.token_out() == order.buy_token
         })
-        .map(|(token_address, _)| token_address)
-        .cloned()
+        .filter(|quote| satisfies_limit_price(order, quote.amount_in(), quote.amount_out()))
+        .copied()
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| {
+        b.amount_out()
+            .full_mul(a.amount_in())
+            .cmp(&a.amount_out().full_mul(b.amount_in()))
+    });
+
+    // What the quote contributes to the fixed amount of `order`.
+    let filling = |quote: &Quote| executed_amount(order, quote.amount_in(), quote.amount_out());
+    let mut fill = Vec::new();
+    let mut filled = U256::zero();
+    for quote in &candidates {
+        match filled.checked_add(filling(quote)) {
+            Some(total) if total <= fixed_amount(order) => {
+                filled = total;
+                fill.push(*quote);
+            }
+            _ => continue,
+        }
+    }
+    if order.allow_partial_fill {
+        return (!fill.is_empty()).then_some(fill);
+    }
+    if filled == fixed_amount(order) {
+        return Some(fill);
+    }
+    // A quote covering the whole order on its own may have been crowded out
+    // by smaller ones with a better rate.
+    candidates
+        .into_iter()
+        .find(|quote| filling(quote) == fixed_amount(order))
+        .map(|quote| vec![quote])
+}
+
+/// Whether the settlement contract already invalidated the maker order with
+/// `order_hash`.
+async fn is_consumed_on_chain(contract: &MooSettlementContract, order_hash: H256) -> Result<bool> {
+    Ok(contract
+        .invalidated_orders(ContractBytes(order_hash.0))
+        .call()
+        .await?)
 }
 
-fn calculate_prices_for_order(
+/// Whether selling `sell_amount` for `buy_amount` is at least as good as the
+/// limit price of `order`.
+fn satisfies_limit_price(order: &OrderModel, sell_amount: U256, buy_amount: U256) -> bool {
+    buy_amount.full_mul(order.sell_amount) >= order.buy_amount.full_mul(sell_amount)
+}
+
+/// Executes an order that was matched at `sell_amount` and `buy_amount` at
+/// the uniform clearing `prices`. Partially fillable orders pay for `gas`,
+/// their share of the gas of the interactions settling them, out of what
+/// they signed to sell: sell orders sell that much less if need be, buy
+/// orders are held to their limit price including the fee, which keeps it
+/// within their sell amount too. Without a gas price or a price of their sell
+/// token they pay their fee in proportion to how much of them gets executed
+/// instead.
+fn executed_order(
+    auction: &Auction,
     order: &OrderModel,
     sell_amount: U256,
     buy_amount: U256,
-    prices: &mut HashMap<H160, U256>,
-) -> Result<()> {
-    match (prices.get(&order.sell_token), prices.get(&order.buy_token)) {
-        (Some(price_sell_token), None) => {
-            prices.insert(
-                order.buy_token,
-                price_sell_token
-                    .checked_mul(sell_amount)
-                    .unwrap()
-                    .checked_div(buy_amount)
-                    .unwrap(),
-            );
+    gas: u64,
+    prices: &HashMap<H160, U256>,
+) -> Result<ExecutedOrderModel> {
+    let gas_fee = order
+        .allow_partial_fill
+        .then(|| gas_fee(auction, order, gas))
+        .flatten();
+    let sell_amount = match gas_fee {
+        Some(fee) if order.is_sell_order => order
+            .sell_amount
+            .checked_sub(fee)
+            .ok_or_else(|| anyhow!("fee exceeds the sell amount"))?
+            .min(sell_amount),
+        _ => sell_amount,
+    };
+    let (sell_amount, buy_amount) = execute_at(order, sell_amount, buy_amount, prices)?;
+    Ok(ExecutedOrderModel {
+        exec_sell_amount: sell_amount,
+        exec_buy_amount: buy_amount,
+        exec_fee_amount: order.allow_partial_fill.then(|| {
+            gas_fee.unwrap_or_else(|| {
+                mul_div(
+                    order.fee.amount,
+                    executed_amount(order, sell_amount, buy_amount),
+                    fixed_amount(order),
+                    Rounding::Down,
+                )
+                .unwrap_or_default()
+            })
+        }),
+    })
+}
+
+/// What `gas` costs at the gas price of the auction in the sell token of
+/// `order`, rounded up.
+fn gas_fee(auction: &Auction, order: &OrderModel, gas: u64) -> Option<U256> {
+    let cost = gas as f64 * auction.gas_price?;
+    objective::token_amount(auction, order.sell_token, cost)
+}
+
+/// The gas price of the node, unless it doesn't answer before `deadline`.
+async fn current_gas_price(web3: &Web3<Http>, deadline: Instant) -> Option<f64> {
+    match tokio::time::timeout_at(deadline, web3.eth().gas_price()).await {
+        Ok(Ok(gas_price)) => Some(objective::to_f64(gas_price)),
+        Ok(Err(err)) => {
+            tracing::warn!(?err, "failed to fetch gas price");
+            None
         }
-        (None, Some(price_buy_token)) => {
-            prices.insert(
-                order.sell_token,
-                price_buy_token
-                    .checked_mul(buy_amount)
-                    .unwrap()
-                    .checked_div(sell_amount)
-                    .unwrap(),
-            );
+        Err(_) => {
+            tracing::warn!("deadline reached while fetching gas price");
+            None
         }
-        _ => return Err(anyhow!("can't deal with such a ring")),
+    }
+}
+
+fn add_maker_order(
+    solution: &mut SettledBatchAuctionModel,
+    maker_order: &SignedOrder,
+    contract: &MooSettlementContract,
+    internal: bool,
+) {
+    let interaction = MooSettlementInteraction {
+        order: maker_order.order.clone(),
+        signature: maker_order.signature.clone(),
+        moo: contract.clone(),
+    }
+    .encode();
+    let encoded_interaction = interaction.first().unwrap();
+
+    solution.approvals.push(ApprovalModel {
+        token: maker_order.order.token_in,
+        spender: encoded_interaction.target,
+        amount: maker_order.order.amount_in,
+    });
+    push_interaction(
+        solution,
+        encoded_interaction,
+        vec![TokenAmount {
+            amount: maker_order.order.amount_in,
+            token: maker_order.order.token_in,
+        }],
+        vec![TokenAmount {
+            amount: maker_order.order.amount_out,
+            token: maker_order.order.token_out,
+        }],
+        internal,
+    );
+}
+
+/// Adds the transfer into the pool and the swap paying out of it. No approval
+/// is needed since the settlement contract transfers the input itself. Both
+/// are internal if the swap is.
+fn add_swap(
+    solution: &mut SettledBatchAuctionModel,
+    pool: &amm::Pool,
+    swap: &amm::Swap,
+    web3: &Web3<Http>,
+    internal: bool,
+) {
+    let interaction = UniswapV2Interaction {
+        pair: IUniswapLikePair::at(web3, pool.address),
+        token_in: ERC20::at(web3, swap.token_in),
+        amount_in: swap.amount_in,
+        amounts_out: pool.amounts_out(swap),
+        receiver: H160::from_str(SETTLEMENT_CONTRACT_ADDRESS).unwrap(),
+    }
+    .encode();
+    let (transfer, swap_call) = (&interaction[0], &interaction[1]);
+    push_interaction(
+        solution,
+        transfer,
+        vec![TokenAmount {
+            amount: swap.amount_in,
+            token: swap.token_in,
+        }],
+        Vec::new(),
+        internal,
+    );
+    push_interaction(
+        solution,
+        swap_call,
+        Vec::new(),
+        vec![TokenAmount {
+            amount: swap.amount_out,
+            token: swap.token_out,
+        }],
+        internal,
+    );
+}
+
+/// Unwraps `amount` of `weth` into native ETH.
+fn unwrap_weth(
+    solution: &mut SettledBatchAuctionModel,
+    weth: H160,
+    amount: U256,
+    web3: &Web3<Http>,
+) {
+    let interaction = WethInteraction {
+        weth: WETH9::at(web3, weth),
+        amount,
+    }
+    .encode();
+    let inputs = vec![TokenAmount {
+        amount,
+        token: weth,
+    }];
+    let outputs = vec![TokenAmount {
+        amount,
+        token: native_token(),
+    }];
+    push_interaction(solution, &interaction[0], inputs, outputs, false);
+}
+
+fn native_token() -> H160 {
+    H160::from_str(NATIVE_TOKEN_ADDRESS).unwrap()
+}
+
+/// `weth` for native ETH, the token itself otherwise.
+fn erc20(token: H160, weth: H160) -> H160 {
+    if token == native_token() {
+        weth
+    } else {
+        token
+    }
+}
+
+/// Appends an interaction, executed after the ones already in `solution`.
+/// Internal interactions are settled from the buffers of the settlement
+/// contract instead if the driver chooses to.
+fn push_interaction(
+    solution: &mut SettledBatchAuctionModel,
+    interaction: &EncodedInteraction,
+    inputs: Vec<TokenAmount>,
+    outputs: Vec<TokenAmount>,
+    internal: bool,
+) {
+    let position = solution.interaction_data.len() as u32;
+    solution.interaction_data.push(InteractionData {
+        target: interaction.target,
+        value: interaction.value,
+        call_data: interaction.call_data.0.clone(),
+        exec_plan: ExecutionPlan {
+            coordinates: ExecutionPlanCoordinatesModel {
+                sequence: 0,
+                position,
+            },
+            internal,
+        },
+        inputs,
+        outputs,
+    });
+}
+
+/// The token to normalize prices by: the one with the highest
+/// `normalize_priority` or, if no token has a higher one, one with an external
+/// price. Ties go to the lowest address.
+fn get_ref_token<'a>(
+    tokens: impl IntoIterator<Item = (&'a H160, &'a TokenInfoModel)>,
+) -> Option<H160> {
+    tokens
+        .into_iter()
+        .min_by_key(|(token, info)| {
+            (
+                Reverse(info.normalize_priority),
+                Reverse(info.external_price.is_some()),
+                **token,
+            )
+        })
+        .map(|(token, _)| *token)
+}
+
+/// The price of a whole unit of a token in its smallest units, assuming 18
+/// decimals for tokens that don't say.
+fn one_unit(info: Option<&TokenInfoModel>) -> U256 {
+    let decimals = info.and_then(|info| info.decimals).unwrap_or(18);
+    U256::from(10)
+        .checked_pow(decimals.into())
+        .unwrap_or_else(|| U256::exp10(18))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::{self, auction, buy_order, maker_order, order, token, trading, weth};
+    use super::*;
+    use crate::models::batch_auction_model::CostModel;
+
+    fn quotes(makers: &[SignedOrder]) -> Vec<Quote<'_>> {
+        makers.iter().map(Quote::Maker).collect()
+    }
+
+    fn uids(fill: Option<Vec<Quote>>) -> Option<Vec<u8>> {
+        fill.map(|fill| {
+            fill.iter()
+                .map(|quote| match quote {
+                    Quote::Maker(maker_order) => maker_order.order.uid.0[0],
+                    Quote::Liquidity(..) => unreachable!(),
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn fills_partially_fillable_orders_best_rate_first() {
+        let makers = [
+            maker_order(1, 60, 120),
+            maker_order(2, 50, 150),
+            maker_order(3, 30, 60),
+            maker_order(4, 10, 10),
+        ];
+        let makers = quotes(&makers);
+        // Maker order 1 doesn't fit anymore after 2 and 3, 4 is below the
+        // limit price.
+        assert_eq!(
+            uids(fill_from_quotes(&order(100, 150, true), &makers)),
+            Some(vec![2, 3])
+        );
+        assert_eq!(uids(fill_from_quotes(&order(10, 100, true), &makers)), None);
+    }
+
+    #[test]
+    fn fills_fill_or_kill_orders_completely() {
+        let makers = [
+            maker_order(1, 50, 150),
+            maker_order(2, 100, 200),
+            maker_order(3, 30, 60),
+        ];
+        let makers = quotes(&makers);
+        assert_eq!(
+            uids(fill_from_quotes(&order(80, 150, false), &makers)),
+            Some(vec![1, 3])
+        );
+        // 1 and 3 don't add up to 100, so 2 has to fill it on its own.
+        assert_eq!(
+            uids(fill_from_quotes(&order(100, 150, false), &makers)),
+            Some(vec![2])
+        );
+        assert_eq!(
+            uids(fill_from_quotes(&order(90, 150, false), &makers)),
+            None
+        );
+    }
+
+    #[test]
+    fn fills_buy_orders_up_to_their_buy_amount() {
+        let makers = [
+            maker_order(1, 50, 150),
+            maker_order(2, 100, 200),
+            maker_order(3, 20, 60),
+        ];
+        let makers = quotes(&makers);
+        assert_eq!(
+            uids(fill_from_quotes(&buy_order(100, 210, false), &makers)),
+            Some(vec![1, 3])
+        );
+        assert_eq!(
+            uids(fill_from_quotes(&buy_order(200, 250, true), &makers)),
+            Some(vec![1, 3])
+        );
+        assert_eq!(
+            uids(fill_from_quotes(&buy_order(100, 200, false), &makers)),
+            Some(vec![2])
+        );
+    }
+
+    fn solve<'a>(auction: &'a Auction, makers: &'a [SignedOrder]) -> Solution<'a> {
+        let quotes = makers
+            .iter()
+            .map(Quote::Maker)
+            .chain(liquidity_orders(&auction.orders))
+            .collect::<Vec<_>>();
+        solve_orders(auction, &quotes, &[], &CANDIDATES[0]).unwrap()
+    }
+
+    fn amounts(executed: &ExecutedOrderModel) -> (U256, U256, Option<U256>) {
+        (
+            executed.exec_sell_amount,
+            executed.exec_buy_amount,
+            executed.exec_fee_amount,
+        )
+    }
+
+    #[test]
+    fn executes_sell_orders_for_their_sell_amount() {
+        let makers = [maker_order(1, 100, 200), maker_order(2, 40, 80)];
+        let auction = auction(vec![order(100, 150, false), order(100, 150, true)]);
+        let solution = solve(&auction, &makers);
+        assert_eq!(
+            amounts(&solution.orders[&0]),
+            (100.into(), 200.into(), None)
+        );
+        assert_eq!(
+            amounts(&solution.orders[&1]),
+            (40.into(), 80.into(), Some(4.into()))
+        );
+        assert_eq!(solution.prices[&token(2)], 500_000.into());
+    }
+
+    #[test]
+    fn executes_buy_orders_for_their_buy_amount() {
+        let makers = [
+            maker_order(1, 45, 90),
+            maker_order(2, 50, 100),
+            maker_order(3, 60, 120),
+        ];
+        let auction = auction(vec![buy_order(60, 90, false), buy_order(200, 300, true)]);
+        let solution = solve(&auction, &makers);
+        assert_eq!(amounts(&solution.orders[&0]), (45.into(), 90.into(), None));
+        // Fee pro-rated by the 220 of 300 bought.
+        assert_eq!(
+            amounts(&solution.orders[&1]),
+            (110.into(), 220.into(), Some(7.into()))
+        );
+        assert_eq!(solution.maker_orders.len(), 3);
+
+        // Not enough to buy for the limit price.
+        let makers = [maker_order(1, 45, 90)];
+        let auction = fixtures::auction(vec![buy_order(40, 90, false)]);
+        let solution = solve(&auction, &makers);
+        assert!(solution.orders.is_empty());
+    }
+
+    #[test]
+    fn skips_maker_orders_below_the_limit_price() {
+        let auction = auction(vec![order(100, 125, false)]);
+        assert!(solve(&auction, &[maker_order(1, 100, 124)])
+            .orders
+            .is_empty());
+        let makers = [maker_order(1, 100, 125)];
+        let solution = solve(&auction, &makers);
+        assert_eq!(
+            amounts(&solution.orders[&0]),
+            (100.into(), 125.into(), None)
+        );
+    }
+
+    #[test]
+    fn uses_every_maker_order_once() {
+        let makers = [maker_order(1, 100, 200)];
+        let auction = auction(vec![order(100, 150, false), order(100, 150, false)]);
+        let solution = solve(&auction, &makers);
+        assert_eq!(solution.orders.keys().collect::<Vec<_>>(), [&0]);
+        assert_eq!(solution.maker_orders.len(), 1);
+    }
+
+    #[test]
+    fn drops_orders_that_dont_clear_at_the_uniform_prices() {
+        let makers = [maker_order(1, 100, 200), maker_order(2, 100, 125)];
+        // Together the orders get about 162 each, below the limit price of the
+        // first one.
+        let auction = auction(vec![order(100, 199, false), order(100, 125, false)]);
+        let solution = solve(&auction, &makers);
+        assert_eq!(solution.orders.keys().collect::<Vec<_>>(), [&1]);
+        assert_eq!(
+            amounts(&solution.orders[&1]),
+            (100.into(), 125.into(), None)
+        );
+        assert_eq!(solution.maker_orders.len(), 1);
+    }
+
+    #[test]
+    fn fills_from_maker_orders_within_the_risk_limits() {
+        let makers = [maker_order(1, 100, 200), maker_order(2, 100, 200)];
+        let mut auction = auction(vec![order(100, 150, false), order(100, 150, false)]);
+        // The maker orders pay out 200 units of the native token each, which
+        // has 18 decimals.
+        auction.risk_limits.per_auction = Some(300e-18);
+        let solution = solve(&auction, &makers);
+        assert_eq!(solution.orders.keys().collect::<Vec<_>>(), [&0]);
+        assert_eq!(solution.maker_orders.len(), 1);
+    }
+
+    #[test]
+    fn picks_the_candidate_with_the_highest_objective_value() {
+        use crate::models::batch_auction_model::{AmmParameters, ConstantProductPoolParameters};
+
+        let auction = auction(vec![order(1000, 1900, false)]);
+        let mut model = BatchAuctionModel {
+            orders: auction.orders,
+            tokens: auction.tokens,
+            ..Default::default()
+        };
+        model.amms = BTreeMap::from([(
+            0,
+            AmmModel {
+                parameters: AmmParameters::ConstantProduct(ConstantProductPoolParameters {
+                    reserves: [(token(1), 1_000_000.into()), (token(2), 3_000_000.into())].into(),
+                }),
+                fee: 0.003,
+                cost: CostModel {
+                    amount: 1000.into(),
+                    token: token(1),
+                },
+                mandatory: false,
+                address: token(3),
+            },
+        )]);
+        let makers = [maker_order(1, 1000, 1950)];
+        let makers = makers.iter().collect::<Vec<_>>();
+        let web3 = Web3::new(Http::new("http://localhost:8545").unwrap());
+        let contract = MooSettlementContract::at(&web3, H160::zero());
+        let strategies = strategy::from_names("naive").unwrap();
+        // The number of maker orders and interactions of the best solution.
+        let best = |model: &BatchAuctionModel, deadline| {
+            let context = ChainContext {
+                web3: &web3,
+                contract: &contract,
+                maker_orders: &makers,
+                gas_price: None,
+                slippage: SlippagePolicy::default(),
+                risk_limits: RiskLimits::default(),
+                weth: weth(),
+                deadline,
+            };
+            let ranked = ranked_solutions(&strategies, model, &context);
+            assert!(ranked
+                .windows(2)
+                .all(|pair| pair[0].objective_value >= pair[1].objective_value));
+            let solution = &ranked[0];
+            (
+                solution.maker_orders.len(),
+                solution.settlement.interaction_data.len(),
+            )
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        // The pool pays 2988 instead of 1950, which outweighs its cost.
+        assert_eq!(best(&model, deadline), (0, 2));
+        // Past the deadline we stick with the first candidate, which prefers
+        // maker orders.
+        assert_eq!(best(&model, Instant::now()), (1, 1));
+        model.amms.get_mut(&0).unwrap().cost.amount = 1100.into();
+        assert_eq!(best(&model, deadline), (1, 1));
+    }
+
+    #[test]
+    fn ranks_distinct_alternative_solutions() {
+        use crate::models::batch_auction_model::{AmmParameters, ConstantProductPoolParameters};
+
+        let auction = auction(vec![order(1000, 1900, false)]);
+        let model = BatchAuctionModel {
+            orders: auction.orders,
+            tokens: auction.tokens,
+            amms: BTreeMap::from([(
+                0,
+                AmmModel {
+                    parameters: AmmParameters::ConstantProduct(ConstantProductPoolParameters {
+                        reserves: [(token(1), 1_000_000.into()), (token(2), 3_000_000.into())]
+                            .into(),
+                    }),
+                    fee: 0.003,
+                    cost: CostModel::default(),
+                    mandatory: false,
+                    address: token(3),
+                },
+            )]),
+            ..Default::default()
+        };
+        let makers = [maker_order(1, 1000, 1950)];
+        let makers = makers.iter().collect::<Vec<_>>();
+        let web3 = Web3::new(Http::new("http://localhost:8545").unwrap());
+        let contract = MooSettlementContract::at(&web3, H160::zero());
+        let context = ChainContext {
+            web3: &web3,
+            contract: &contract,
+            maker_orders: &makers,
+            gas_price: None,
+            slippage: SlippagePolicy::default(),
+            risk_limits: RiskLimits::default(),
+            weth: weth(),
+            deadline: Instant::now() + Duration::from_secs(60),
+        };
+        let ranked = ranked_solutions(&strategy::from_names("naive").unwrap(), &model, &context);
+        // Only the pool and the maker order, however many candidates use them.
+        assert_eq!(
+            ranked
+                .iter()
+                .map(|solved| solved.maker_orders.len())
+                .collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert!(ranked[0].objective_value > ranked[1].objective_value);
+    }
+
+    #[test]
+    fn leaves_room_for_slippage_on_swaps() {
+        let swap = amm::Swap {
+            pool: 0,
+            token_in: token(1),
+            amount_in: 1000.into(),
+            token_out: token(2),
+            amount_out: 2000.into(),
+        };
+        let mut auction = auction(Vec::new());
+        auction.slippage.relative_bps = 100;
+        let swapped = |order: &OrderModel| {
+            with_slippage(&auction, order, swap.clone())
+                .map(|swap| (swap.amount_in, swap.amount_out))
+        };
+        assert_eq!(
+            swapped(&order(1000, 1900, false)),
+            Some((1000.into(), 1980.into()))
+        );
+        assert_eq!(
+            swapped(&buy_order(1100, 2000, false)),
+            Some((1010.into(), 2000.into()))
+        );
+        assert_eq!(swapped(&order(1000, 1990, false)), None);
+    }
+
+    #[test]
+    fn unwraps_the_native_eth_orders_buy() {
+        let info = TokenInfoModel {
+            external_price: Some(1.),
+            ..Default::default()
+        };
+        let model = BatchAuctionModel {
+            orders: BTreeMap::from([(
+                0,
+                OrderModel {
+                    buy_token: native_token(),
+                    ..order(100, 150, false)
+                },
+            )]),
+            tokens: BTreeMap::from([(token(1), info.clone()), (native_token(), info)]),
+            ..Default::default()
+        };
+        // A maker who only holds WETH.
+        let mut maker = maker_order(1, 100, 200);
+        maker.order.token_out = weth();
+        let web3 = Web3::new(Http::new("http://localhost:8545").unwrap());
+        let contract = MooSettlementContract::at(&web3, H160::zero());
+        let context = ChainContext {
+            web3: &web3,
+            contract: &contract,
+            maker_orders: &[&maker],
+            gas_price: None,
+            slippage: SlippagePolicy::default(),
+            risk_limits: RiskLimits::default(),
+            weth: weth(),
+            deadline: Instant::now() + Duration::from_secs(60),
+        };
+
+        let auction = Auction::new(&model, &context).unwrap();
+        let solutions = candidate_solutions(&auction, &CANDIDATES[..1], &context);
+        let settlement = &solutions[0].settlement;
+        assert_eq!(
+            amounts(&settlement.orders[&0]),
+            (100.into(), 200.into(), None)
+        );
+        assert_eq!(
+            settlement.prices[&native_token()],
+            settlement.prices[&weth()]
+        );
+        let unwrap = settlement.interaction_data.last().unwrap();
+        assert_eq!(unwrap.target, weth());
+        assert_eq!(
+            (unwrap.inputs[0].token, unwrap.inputs[0].amount),
+            (weth(), 200.into())
+        );
+        assert_eq!(
+            (unwrap.outputs[0].token, unwrap.outputs[0].amount),
+            (native_token(), 200.into())
+        );
+    }
+
+    #[test]
+    fn doesnt_execute_orders_selling_native_eth() {
+        let info = TokenInfoModel {
+            external_price: Some(1.),
+            ..Default::default()
+        };
+        let model = BatchAuctionModel {
+            orders: BTreeMap::from([
+                (0, trading(order(100, 150, false), native_token(), token(2))),
+                (1, order(100, 150, false)),
+            ]),
+            tokens: BTreeMap::from([
+                (token(1), info.clone()),
+                (token(2), info.clone()),
+                (native_token(), info),
+            ]),
+            ..Default::default()
+        };
+        // A maker who would take the WETH the native ETH order sells.
+        let mut weth_maker = maker_order(1, 100, 200);
+        weth_maker.order.token_in = weth();
+        let maker = maker_order(2, 100, 200);
+        let web3 = Web3::new(Http::new("http://localhost:8545").unwrap());
+        let contract = MooSettlementContract::at(&web3, H160::zero());
+        let context = ChainContext {
+            web3: &web3,
+            contract: &contract,
+            maker_orders: &[&weth_maker, &maker],
+            gas_price: None,
+            slippage: SlippagePolicy::default(),
+            risk_limits: RiskLimits::default(),
+            weth: weth(),
+            deadline: Instant::now() + Duration::from_secs(60),
+        };
+
+        let auction = Auction::new(&model, &context).unwrap();
+        let solutions = candidate_solutions(&auction, &CANDIDATES[..1], &context);
+        let settlement = &solutions[0].settlement;
+        assert_eq!(settlement.orders.keys().collect::<Vec<_>>(), [&1]);
+        assert!(settlement
+            .interaction_data
+            .iter()
+            .all(|interaction| interaction.target != weth()));
+    }
+
+    #[test]
+    fn fills_user_orders_from_liquidity_orders() {
+        let liquidity_order = OrderModel {
+            sell_token: token(2),
+            buy_token: token(1),
+            is_liquidity_order: true,
+            ..order(200, 100, false)
+        };
+        let auction = auction(vec![order(100, 150, false), liquidity_order.clone()]);
+        let solution = solve(&auction, &[]);
+        assert_eq!(
+            amounts(&solution.orders[&0]),
+            (100.into(), 200.into(), None)
+        );
+        // At exactly its limit price.
+        assert_eq!(
+            amounts(&solution.orders[&1]),
+            (200.into(), 100.into(), None)
+        );
+        assert!(solution.maker_orders.is_empty());
+
+        // Liquidity orders aren't matched against each other like user orders.
+        let opposite = OrderModel {
+            is_liquidity_order: true,
+            ..order(100, 150, false)
+        };
+        let auction = fixtures::auction(vec![opposite, liquidity_order]);
+        assert!(solve(&auction, &[]).orders.is_empty());
+    }
+
+    #[test]
+    fn settles_from_buffers_internally() {
+        let makers = [maker_order(1, 100, 200), maker_order(2, 100, 200)];
+        let mut auction = auction(vec![order(100, 150, true), order(100, 150, true)]);
+        auction.tokens.get_mut(&token(2)).unwrap().internal_buffer = Some(300.into());
+        auction.gas_price = Some(1e-4);
+        // The buffer covers only one of the maker orders, whose order then
+        // doesn't pay for any gas.
+        let solution = solve(&auction, &makers);
+        assert_eq!(
+            solution.internal_quotes,
+            HashSet::from([QuoteId::Maker(&makers[0].order.uid)])
+        );
+        assert_eq!(
+            amounts(&solution.orders[&0]),
+            (100.into(), 200.into(), Some(0.into()))
+        );
+        assert_eq!(
+            amounts(&solution.orders[&1]),
+            (89.into(), 178.into(), Some(11.into()))
+        );
+    }
+
+    #[test]
+    fn executes_no_more_orders_than_allowed() {
+        let makers = [
+            maker_order(1, 100, 200),
+            maker_order(2, 100, 195),
+            maker_order(3, 100, 190),
+        ];
+        let mut auction = auction(vec![
+            order(100, 150, false),
+            order(100, 180, false),
+            order(100, 190, false),
+        ]);
+        assert_eq!(solve(&auction, &makers).orders.len(), 3);
+
+        // The order with the tightest limit price has the least surplus.
+        auction.max_nr_exec_orders = Some(2);
+        let solution = solve(&auction, &makers);
+        let mut executed = solution.orders.keys().copied().collect::<Vec<_>>();
+        executed.sort();
+        assert_eq!(executed, [0, 1]);
+        assert_eq!(solution.maker_orders.len(), 2);
+    }
+
+    #[test]
+    fn picks_reference_tokens() {
+        let info = |normalize_priority, external_price| TokenInfoModel {
+            normalize_priority,
+            external_price,
+            ..Default::default()
+        };
+        let mut tokens = BTreeMap::from([
+            (token(1), info(None, None)),
+            (token(2), info(None, Some(2.))),
+            (token(3), info(None, Some(1.))),
+        ]);
+        assert_eq!(get_ref_token(&tokens), Some(token(2)));
+        tokens.get_mut(&token(3)).unwrap().normalize_priority = Some(1);
+        assert_eq!(get_ref_token(&tokens), Some(token(3)));
+        assert_eq!(get_ref_token(&BTreeMap::new()), None);
+    }
+
+    #[test]
+    fn normalizes_disjoint_groups_relative_to_the_reference_token() {
+        let mut auction = auction(Vec::new());
+        auction.tokens.get_mut(&token(2)).unwrap().external_price = Some(0.5);
+        assert_eq!(
+            auction.reference(&BTreeSet::from([token(1), token(2)])),
+            Some((token(1), 1_000_000.into()))
+        );
+        assert_eq!(
+            auction.reference(&BTreeSet::from([token(2), token(3)])),
+            Some((token(2), 500_000.into()))
+        );
+        // Without an external price, a whole unit of 18 decimals.
+        assert_eq!(
+            auction.reference(&BTreeSet::from([token(3), token(4)])),
+            Some((token(3), U256::exp10(18)))
+        );
+    }
+
+    #[test]
+    fn keeps_the_most_valuable_matches() {
+        let matches = [(2, 100.), (1, 60.), (1, 50.)];
+        assert_eq!(most_valuable(&matches, 1), [false, true, false]);
+        assert_eq!(most_valuable(&matches, 2), [false, true, true]);
+        assert_eq!(most_valuable(&matches, 3), [true, true, false]);
+    }
+
+    #[test]
+    fn pro_rates_fees_of_partial_fills_without_gas_price() {
+        let auction = auction(Vec::new());
+        // Token 1 is worth twice as much as token 2.
+        let prices = HashMap::from([(token(1), 2.into()), (token(2), 1.into())]);
+        let executed = |order, sell_amount: u64, buy_amount: u64| {
+            executed_order(
+                &auction,
+                &order,
+                sell_amount.into(),
+                buy_amount.into(),
+                MAKER_ORDER_GAS,
+                &prices,
+            )
+            .unwrap()
+            .exec_fee_amount
+        };
+        assert_eq!(executed(order(100, 150, true), 40, 80), Some(4.into()));
+        assert_eq!(executed(order(100, 150, false), 100, 200), None);
+        assert_eq!(executed(buy_order(100, 150, true), 40, 75), Some(5.into()));
     }
 
-    Ok(())
+    #[test]
+    fn charges_partial_fills_for_their_gas() {
+        let makers = [maker_order(1, 100, 200)];
+        let mut auction = auction(vec![order(100, 150, true)]);
+        auction.gas_price = Some(1e-4);
+        // 11 of the sell token for the gas of one maker order, which come out
+        // of the 100 the order sells.
+        let solution = solve(&auction, &makers);
+        assert_eq!(
+            amounts(&solution.orders[&0]),
+            (89.into(), 178.into(), Some(11.into()))
+        );
+
+        // Selling 100 including the fee for 178 is below the limit price.
+        auction.orders.get_mut(&0).unwrap().buy_amount = 190.into();
+        assert!(solve(&auction, &makers).orders.is_empty());
+    }
 }
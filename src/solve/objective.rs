@@ -0,0 +1,213 @@
+//! The objective the auction ranks solutions by: the surplus the executed
+//! orders get over their limit prices plus the fees they pay, minus what it
+//! costs to execute them, all valued in the native token. Interactions the
+//! buffers of the settlement contract cover don't cost anything.
+
+use super::quote::{Quote, QuoteId};
+use super::{amm::Swap, executed_orders, Auction, Match, Solution, MAKER_ORDER_GAS};
+use crate::models::batch_auction_model::ExecutedOrderModel;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use web3::types::{H160, U256};
+
+pub fn objective_value(auction: &Auction, solution: &Solution) -> f64 {
+    let maker_orders = solution
+        .maker_orders
+        .iter()
+        .filter(|maker_order| {
+            !solution
+                .internal_quotes
+                .contains(&QuoteId::Maker(&maker_order.order.uid))
+        })
+        .count();
+    orders_value(auction, &solution.orders)
+        - interactions_cost(
+            auction,
+            &solution.swaps,
+            &solution.internal_pools,
+            maker_orders,
+        )
+}
+
+/// What `order_match` adds to the objective value of a solution settling at
+/// the uniform clearing `prices`.
+pub fn match_value(
+    auction: &Auction,
+    order_match: &Match,
+    prices: &HashMap<H160, U256>,
+) -> Result<f64> {
+    let executed = executed_orders(auction, order_match, prices)?;
+    let maker_orders = order_match
+        .quotes
+        .iter()
+        .filter(|quote| {
+            matches!(quote, Quote::Maker(_)) && !order_match.internal_quotes.contains(&quote.id())
+        })
+        .count();
+    Ok(orders_value(
+        auction,
+        executed.iter().map(|(index, order)| (index, order)),
+    ) - interactions_cost(
+        auction,
+        &order_match.swaps,
+        &order_match.internal_pools,
+        maker_orders,
+    ))
+}
+
+/// The value of the executed `orders`, keyed by their index in the auction.
+fn orders_value<'a>(
+    auction: &Auction,
+    orders: impl IntoIterator<Item = (&'a usize, &'a ExecutedOrderModel)>,
+) -> f64 {
+    orders
+        .into_iter()
+        .map(|(index, executed)| order_value(auction, *index, executed))
+        .sum()
+}
+
+/// What executing `swaps` and `maker_orders` maker orders on chain costs.
+/// Swaps on `internal_pools` don't cost anything.
+fn interactions_cost(
+    auction: &Auction,
+    swaps: &[Swap],
+    internal_pools: &HashSet<usize>,
+    maker_orders: usize,
+) -> f64 {
+    swaps
+        .iter()
+        .filter(|swap| !internal_pools.contains(&swap.pool))
+        .map(|swap| swap_cost(auction, swap))
+        .sum::<f64>()
+        + maker_orders_cost(auction, maker_orders)
+}
+
+/// The surplus and fee of an executed order minus the cost of executing it.
+/// Liquidity orders don't get any surplus.
+fn order_value(auction: &Auction, index: usize, executed: &ExecutedOrderModel) -> f64 {
+    let order = &auction.orders[&index];
+    let (sell_amount, buy_amount) = (
+        to_f64(executed.exec_sell_amount),
+        to_f64(executed.exec_buy_amount),
+    );
+    let surplus = if order.is_liquidity_order {
+        0.
+    } else if order.is_sell_order {
+        let limit = sell_amount * to_f64(order.buy_amount) / to_f64(order.sell_amount);
+        native(auction, order.buy_token, buy_amount - limit)
+    } else {
+        let limit = buy_amount * to_f64(order.sell_amount) / to_f64(order.buy_amount);
+        native(auction, order.sell_token, limit - sell_amount)
+    };
+    let fee = executed.exec_fee_amount.unwrap_or(order.fee.amount);
+    surplus + native(auction, order.fee.token, to_f64(fee))
+        - native(auction, order.cost.token, to_f64(order.cost.amount))
+}
+
+fn swap_cost(auction: &Auction, swap: &Swap) -> f64 {
+    auction.amms.get(&swap.pool).map_or(0., |amm| {
+        native(auction, amm.cost.token, to_f64(amm.cost.amount))
+    })
+}
+
+/// The gas of settling `count` maker orders on chain.
+fn maker_orders_cost(auction: &Auction, count: usize) -> f64 {
+    (count as u64 * MAKER_ORDER_GAS) as f64 * auction.gas_price.unwrap_or_default()
+}
+
+/// Values `amount` of `token` in the native token, tokens without an external
+/// price are worth nothing.
+fn native(auction: &Auction, token: H160, amount: f64) -> f64 {
+    amount
+        * auction
+            .tokens
+            .get(&token)
+            .and_then(|token| token.external_price)
+            .unwrap_or_default()
+}
+
+/// How much of `token` is worth `value` in the native token, rounded up.
+/// `None` for tokens without a positive external price and amounts that
+/// don't fit.
+pub fn token_amount(auction: &Auction, token: H160, value: f64) -> Option<U256> {
+    let price = auction.tokens.get(&token)?.external_price?;
+    if price <= 0. {
+        return None;
+    }
+    from_f64((value / price).ceil())
+}
+
+/// `None` for values that are negative or don't fit.
+pub fn from_f64(value: f64) -> Option<U256> {
+    (0. ..2f64.powi(128))
+        .contains(&value)
+        .then(|| U256::from(value as u128))
+}
+
+pub fn to_f64(amount: U256) -> f64 {
+    amount
+        .0
+        .iter()
+        .rev()
+        .fold(0., |value, word| value * 2f64.powi(64) + *word as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::batch_auction_model::{CostModel, OrderModel};
+    use crate::solve::fixtures::{auction, buy_order, order, token};
+
+    #[test]
+    fn values_surplus_and_fees_minus_costs() {
+        let cost = CostModel {
+            amount: 4.into(),
+            token: token(1),
+        };
+        let mut auction = auction(vec![
+            OrderModel {
+                cost: cost.clone(),
+                ..order(100, 150, false)
+            },
+            OrderModel {
+                cost,
+                ..buy_order(100, 150, false)
+            },
+        ]);
+        auction.tokens.get_mut(&token(2)).unwrap().external_price = Some(0.5);
+        let executed = |sell_amount: u64, buy_amount: u64| ExecutedOrderModel {
+            exec_sell_amount: sell_amount.into(),
+            exec_buy_amount: buy_amount.into(),
+            exec_fee_amount: None,
+        };
+        let solution = Solution {
+            orders: HashMap::from([(0, executed(100, 200)), (1, executed(75, 150))]),
+            maker_orders: Vec::new(),
+            swaps: Vec::new(),
+            prices: HashMap::new(),
+            internal_quotes: HashSet::new(),
+            internal_pools: HashSet::new(),
+        };
+        // 50 of token 2 and 25 of token 1 surplus, twice 10 fees and 4 costs.
+        assert_eq!(
+            objective_value(&auction, &solution),
+            25. + 25. + 2. * (10. - 4.)
+        );
+    }
+
+    #[test]
+    fn converts_big_amounts() {
+        assert_eq!(to_f64(U256::exp10(30)), 1e30);
+        assert_eq!(to_f64(U256::MAX), 2f64.powi(256));
+    }
+
+    #[test]
+    fn converts_native_values_to_token_amounts() {
+        let mut auction = auction(Vec::new());
+        auction.tokens.get_mut(&token(1)).unwrap().external_price = Some(0.5);
+        assert_eq!(token_amount(&auction, token(1), 10.), Some(20.into()));
+        assert_eq!(token_amount(&auction, token(1), 10.2), Some(21.into()));
+        assert_eq!(token_amount(&auction, token(3), 10.), None);
+        assert_eq!(token_amount(&auction, token(1), f64::INFINITY), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Uniform clearing prices over the token graph of a solution.
+
+use super::arithmetic::{mul_div, Rounding};
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use web3::types::{H160, U256};
+
+/// An executed trade the clearing prices should reproduce.
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub sell_token: H160,
+    pub buy_token: H160,
+    pub sell_amount: U256,
+    pub buy_amount: U256,
+}
+
+/// Assigns a price to every token traded in `trades`, normalized separately
+/// for every group of tokens connected through trades.
+///
+/// Trades on the same token pair are aggregated in both directions, so every
+/// pair gets the volume weighted exchange rate of its trades. `reference`
+/// picks the token of a group to normalize its prices by, along with that
+/// token's price, and prices are then propagated breadth first from it, so
+/// every token is priced along its shortest path of pairs. Pairs closing a
+/// cycle don't change prices that are already set; whether the trades on them
+/// are still acceptable at those prices is up to the caller to check. Groups
+/// `reference` has no token for don't get prices.
+pub fn clearing_prices(
+    trades: &[Trade],
+    reference: impl Fn(&BTreeSet<H160>) -> Option<(H160, U256)>,
+) -> Result<HashMap<H160, U256>> {
+    // Amounts traded per pair, keyed and ordered by the lower token address.
+    let mut pairs: Vec<((H160, H160), (U256, U256))> = Vec::new();
+    for trade in trades {
+        let (key, amounts) = if trade.sell_token < trade.buy_token {
+            (
+                (trade.sell_token, trade.buy_token),
+                (trade.sell_amount, trade.buy_amount),
+            )
+        } else {
+            (
+                (trade.buy_token, trade.sell_token),
+                (trade.buy_amount, trade.sell_amount),
+            )
+        };
+        match pairs.iter_mut().find(|(pair, _)| *pair == key) {
+            Some((_, total)) => {
+                total.0 = total
+                    .0
+                    .checked_add(amounts.0)
+                    .ok_or_else(|| anyhow!("traded amount overflow"))?;
+                total.1 = total
+                    .1
+                    .checked_add(amounts.1)
+                    .ok_or_else(|| anyhow!("traded amount overflow"))?;
+            }
+            None => pairs.push((key, amounts)),
+        }
+    }
+
+    let mut prices = HashMap::new();
+    let mut grouped = BTreeSet::new();
+    for ((token, _), _) in &pairs {
+        if grouped.contains(token) {
+            continue;
+        }
+        let group = connected(&pairs, *token);
+        grouped.extend(group.iter().copied());
+        if let Some((ref_token, ref_price)) = reference(&group) {
+            price_group(&pairs, ref_token, ref_price, &mut prices)?;
+        }
+    }
+    Ok(prices)
+}
+
+type Pair = ((H160, H160), (U256, U256));
+
+/// The tokens connected to `token` through `pairs`, itself included.
+fn connected(pairs: &[Pair], token: H160) -> BTreeSet<H160> {
+    let mut group = BTreeSet::from([token]);
+    let mut queue = VecDeque::from([token]);
+    while let Some(token) = queue.pop_front() {
+        for ((token_0, token_1), _) in pairs {
+            let other = if *token_0 == token {
+                *token_1
+            } else if *token_1 == token {
+                *token_0
+            } else {
+                continue;
+            };
+            if group.insert(other) {
+                queue.push_back(other);
+            }
+        }
+    }
+    group
+}
+
+fn price_group(
+    pairs: &[Pair],
+    ref_token: H160,
+    ref_price: U256,
+    prices: &mut HashMap<H160, U256>,
+) -> Result<()> {
+    prices.insert(ref_token, ref_price);
+    let mut queue = VecDeque::from([ref_token]);
+    while let Some(token) = queue.pop_front() {
+        let price = prices[&token];
+        for ((token_0, token_1), (amount_0, amount_1)) in pairs {
+            // Value is conserved along the pair: price_0 * amount_0 == price_1 * amount_1.
+            let (other, other_price) = if *token_0 == token {
+                (
+                    *token_1,
+                    mul_div(price, *amount_0, *amount_1, Rounding::Down),
+                )
+            } else if *token_1 == token {
+                (
+                    *token_0,
+                    mul_div(price, *amount_1, *amount_0, Rounding::Down),
+                )
+            } else {
+                continue;
+            };
+            if prices.contains_key(&other) {
+                continue;
+            }
+            let other_price =
+                other_price.map_err(|err| anyhow!("can't price token {other:?}: {err}"))?;
+            if other_price.is_zero() {
+                return Err(anyhow!("price of token {other:?} rounds to zero"));
+            }
+            prices.insert(other, other_price);
+            queue.push_back(other);
+        }
+    }
+    Ok(())
+}
+
+/// The exchange rate between two tokens at the given clearing prices, as a
+/// sell amount and the buy amount worth the same.
+pub fn exchange_rate(
+    prices: &HashMap<H160, U256>,
+    sell_token: H160,
+    buy_token: H160,
+) -> Option<(U256, U256)> {
+    Some((*prices.get(&buy_token)?, *prices.get(&sell_token)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solve::fixtures::token;
+
+    /// Normalizes by token 1 where it is traded.
+    fn reference(price: u64) -> impl Fn(&BTreeSet<H160>) -> Option<(H160, U256)> {
+        move |group| group.contains(&token(1)).then(|| (token(1), price.into()))
+    }
+
+    fn trade(sell_token: u8, buy_token: u8, sell_amount: u64, buy_amount: u64) -> Trade {
+        Trade {
+            sell_token: token(sell_token),
+            buy_token: token(buy_token),
+            sell_amount: sell_amount.into(),
+            buy_amount: buy_amount.into(),
+        }
+    }
+
+    #[test]
+    fn prices_connected_tokens() {
+        let trades = [trade(1, 2, 100, 200), trade(3, 2, 50, 400)];
+        let prices = clearing_prices(&trades, reference(1_000_000)).unwrap();
+        assert_eq!(prices[&token(1)], 1_000_000.into());
+        assert_eq!(prices[&token(2)], 500_000.into());
+        assert_eq!(prices[&token(3)], 4_000_000.into());
+    }
+
+    #[test]
+    fn uses_volume_weighted_rate_per_pair() {
+        // Both directions of the same pair at a rate of two.
+        let trades = [trade(1, 2, 100, 200), trade(2, 1, 100, 50)];
+        let prices = clearing_prices(&trades, reference(1_000_000)).unwrap();
+        assert_eq!(prices[&token(2)], 500_000.into());
+        assert_eq!(
+            exchange_rate(&prices, token(2), token(1)),
+            Some((1_000_000.into(), 500_000.into()))
+        );
+    }
+
+    #[test]
+    fn prices_cycles_along_shortest_paths() {
+        let trades = [
+            trade(1, 2, 100, 200),
+            trade(2, 3, 200, 300),
+            trade(3, 1, 300, 90),
+        ];
+        let prices = clearing_prices(&trades, reference(600)).unwrap();
+        assert_eq!(prices[&token(2)], 300.into());
+        // Priced through its direct pair with the reference token rather than
+        // through token 2.
+        assert_eq!(prices[&token(3)], 180.into());
+    }
+
+    #[test]
+    fn normalizes_every_group_of_connected_tokens() {
+        let trades = [trade(1, 2, 100, 200), trade(3, 4, 100, 200)];
+        let prices = clearing_prices(&trades, reference(1_000_000)).unwrap();
+        assert_eq!(prices.len(), 2);
+        assert!(!prices.contains_key(&token(3)));
+
+        let prices = clearing_prices(&trades, |group: &BTreeSet<H160>| {
+            let ref_token = *group.first()?;
+            Some((ref_token, 1_000.into()))
+        })
+        .unwrap();
+        assert_eq!(prices[&token(2)], 500.into());
+        assert_eq!(prices[&token(3)], 1_000.into());
+        assert_eq!(prices[&token(4)], 500.into());
+    }
+}
@@ -0,0 +1,62 @@
+//! Liquidity that trades fixed amounts with the settlement contract.
+
+use crate::models::batch_auction_model::OrderModel;
+use crate::models::settlement_contract_data::SignedOrder;
+use web3::types::{Bytes, H160, U256};
+
+/// Counter-liquidity for user orders that takes in and pays out exactly its
+/// amounts, named from its own point of view: it takes `amount_in` of
+/// `token_in` from the settlement contract and pays `amount_out` of
+/// `token_out` into it.
+#[derive(Clone, Copy, Debug)]
+pub enum Quote<'a> {
+    /// A signed maker order, settled through `MooSettlementContract`.
+    Maker(&'a SignedOrder),
+    /// A liquidity order of the auction with its index, which is only ever
+    /// executed in full at exactly its limit price.
+    Liquidity(usize, &'a OrderModel),
+}
+
+/// Identifies a quote so that a solution uses it at most once.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum QuoteId<'a> {
+    Maker(&'a Bytes),
+    Liquidity(usize),
+}
+
+impl<'a> Quote<'a> {
+    pub fn id(&self) -> QuoteId<'a> {
+        match self {
+            Quote::Maker(maker_order) => QuoteId::Maker(&maker_order.order.uid),
+            Quote::Liquidity(index, _) => QuoteId::Liquidity(*index),
+        }
+    }
+
+    pub fn token_in(&self) -> H160 {
+        match self {
+            Quote::Maker(maker_order) => maker_order.order.token_in,
+            Quote::Liquidity(_, order) => order.buy_token,
+        }
+    }
+
+    pub fn amount_in(&self) -> U256 {
+        match self {
+            Quote::Maker(maker_order) => maker_order.order.amount_in,
+            Quote::Liquidity(_, order) => order.buy_amount,
+        }
+    }
+
+    pub fn token_out(&self) -> H160 {
+        match self {
+            Quote::Maker(maker_order) => maker_order.order.token_out,
+            Quote::Liquidity(_, order) => order.sell_token,
+        }
+    }
+
+    pub fn amount_out(&self) -> U256 {
+        match self {
+            Quote::Maker(maker_order) => maker_order.order.amount_out,
+            Quote::Liquidity(_, order) => order.sell_amount,
+        }
+    }
+}
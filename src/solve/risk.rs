@@ -0,0 +1,184 @@
+//! Caps on how much Moo maker orders may fill in a single auction, so that
+//! one mispriced maker quote can't take over the batch.
+
+use super::objective::to_f64;
+use super::quote::Quote;
+use super::slippage::NATIVE_TOKEN_UNIT;
+use crate::models::batch_auction_model::TokenInfoModel;
+use std::collections::{BTreeMap, HashMap};
+use web3::types::{H160, U256};
+
+/// The most notional, in native token units, maker orders may add up to. The
+/// notional of a maker order is what the larger of its two sides is worth.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RiskLimits {
+    /// Across the maker orders of each maker.
+    pub per_maker: Option<f64>,
+    /// Across the maker orders trading each token, on either side.
+    pub per_token: Option<f64>,
+    /// Across all maker orders of an auction.
+    pub per_auction: Option<f64>,
+}
+
+impl RiskLimits {
+    fn is_unlimited(&self) -> bool {
+        self.per_maker.is_none() && self.per_token.is_none() && self.per_auction.is_none()
+    }
+}
+
+/// The notional of the maker orders a solution fills so far.
+#[derive(Clone, Debug, Default)]
+pub struct Exposure {
+    makers: HashMap<H160, f64>,
+    tokens: HashMap<H160, f64>,
+    total: f64,
+}
+
+impl Exposure {
+    /// Whether the maker orders among `quotes` fit into `limits` on top of
+    /// the ones already filled. Maker orders that can't be valued only fit
+    /// when there are no limits.
+    pub fn admits(
+        &self,
+        limits: &RiskLimits,
+        tokens: &BTreeMap<H160, TokenInfoModel>,
+        quotes: &[Quote],
+    ) -> bool {
+        self.with(limits, tokens, quotes).is_some()
+    }
+
+    /// Adds the maker orders among `quotes` if they fit into `limits`.
+    pub fn try_add(
+        &mut self,
+        limits: &RiskLimits,
+        tokens: &BTreeMap<H160, TokenInfoModel>,
+        quotes: &[Quote],
+    ) -> bool {
+        match self.with(limits, tokens, quotes) {
+            Some(exposure) => {
+                *self = exposure;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn with(
+        &self,
+        limits: &RiskLimits,
+        tokens: &BTreeMap<H160, TokenInfoModel>,
+        quotes: &[Quote],
+    ) -> Option<Self> {
+        if limits.is_unlimited() {
+            return Some(self.clone());
+        }
+        let value = |token: H160, amount: U256| {
+            let price = tokens.get(&token)?.external_price?;
+            (price > 0.).then(|| to_f64(amount) * price / NATIVE_TOKEN_UNIT)
+        };
+        let mut exposure = self.clone();
+        for quote in quotes {
+            let Quote::Maker(maker_order) = quote else {
+                continue;
+            };
+            let order = &maker_order.order;
+            let notional = match (
+                value(order.token_in, order.amount_in),
+                value(order.token_out, order.amount_out),
+            ) {
+                (Some(value_in), Some(value_out)) => value_in.max(value_out),
+                (Some(value), None) | (None, Some(value)) => value,
+                (None, None) => return None,
+            };
+            *exposure.makers.entry(order.maker).or_default() += notional;
+            for token in [order.token_in, order.token_out] {
+                *exposure.tokens.entry(token).or_default() += notional;
+            }
+            exposure.total += notional;
+        }
+        let within =
+            |limit: Option<f64>, notional: f64| limit.is_none_or(|limit| notional <= limit);
+        (exposure
+            .makers
+            .values()
+            .all(|notional| within(limits.per_maker, *notional))
+            && exposure
+                .tokens
+                .values()
+                .all(|notional| within(limits.per_token, *notional))
+            && within(limits.per_auction, exposure.total))
+        .then_some(exposure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::settlement_contract_data::SignedOrder;
+    use crate::solve::fixtures::{self, token};
+
+    /// Takes in `ether` of `token_in` for twice that of token 2, which is
+    /// worth half as much as token 1.
+    fn ether_order(maker: u8, token_in: u8, ether: u64) -> SignedOrder {
+        let mut maker_order = fixtures::maker_order(0, 0, 0);
+        let amount = U256::exp10(18) * ether;
+        maker_order.order.token_in = token(token_in);
+        maker_order.order.amount_in = amount;
+        maker_order.order.amount_out = amount * 2;
+        maker_order.order.maker = H160::from_low_u64_be(maker.into());
+        maker_order
+    }
+
+    #[test]
+    fn caps_notional_per_maker_token_and_auction() {
+        let tokens = BTreeMap::from([
+            (
+                token(1),
+                TokenInfoModel {
+                    external_price: Some(1.),
+                    ..Default::default()
+                },
+            ),
+            (
+                token(2),
+                TokenInfoModel {
+                    external_price: Some(0.5),
+                    ..Default::default()
+                },
+            ),
+            (token(3), TokenInfoModel::default()),
+        ]);
+        let makers = [
+            ether_order(1, 1, 3),
+            ether_order(1, 1, 2),
+            ether_order(2, 1, 2),
+            ether_order(3, 3, 1),
+        ];
+        let quotes = makers.iter().map(Quote::Maker).collect::<Vec<_>>();
+        let admitted = |limits: RiskLimits| {
+            let mut exposure = Exposure::default();
+            quotes
+                .iter()
+                .map(|quote| exposure.try_add(&limits, &tokens, &[*quote]))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(admitted(RiskLimits::default()), [true; 4]);
+        let per_maker = RiskLimits {
+            per_maker: Some(4.),
+            ..Default::default()
+        };
+        assert_eq!(admitted(per_maker), [true, false, true, true]);
+        let per_token = RiskLimits {
+            per_token: Some(5.),
+            ..Default::default()
+        };
+        assert_eq!(admitted(per_token), [true, true, false, false]);
+        let per_auction = RiskLimits {
+            per_auction: Some(6.),
+            ..Default::default()
+        };
+        // Only what the unpriced token 3 trades for counts.
+        assert_eq!(admitted(per_auction), [true, true, false, true]);
+    }
+}
@@ -0,0 +1,84 @@
+//! How much worse than computed on-chain swaps may turn out when they get
+//! executed.
+
+use super::arithmetic::{mul_div, Rounding};
+use super::objective;
+use anyhow::{ensure, Result};
+use web3::types::U256;
+
+const BPS_BASE: u64 = 10_000;
+/// Amounts in the native token have 18 decimals.
+pub(super) const NATIVE_TOKEN_UNIT: f64 = 1e18;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SlippagePolicy {
+    /// The tolerance relative to the computed amount, in basis points.
+    pub relative_bps: u32,
+    /// Caps the relative tolerance at what this much of the native token is
+    /// worth.
+    pub absolute_in_native_token: Option<f64>,
+}
+
+/// Parses a relative tolerance in basis points, which can't exceed the whole
+/// amount.
+pub fn parse_relative_bps(value: &str) -> Result<u32> {
+    let bps = value.parse()?;
+    ensure!(
+        u64::from(bps) <= BPS_BASE,
+        "relative slippage of {bps} bps exceeds {BPS_BASE}"
+    );
+    Ok(bps)
+}
+
+impl SlippagePolicy {
+    /// How much `amount` of a token with the given `external_price` may be
+    /// off by.
+    pub fn tolerance(&self, amount: U256, external_price: Option<f64>) -> U256 {
+        let relative = mul_div(
+            amount,
+            self.relative_bps.into(),
+            BPS_BASE.into(),
+            Rounding::Down,
+        )
+        .unwrap_or_default();
+        let absolute = self
+            .absolute_in_native_token
+            .zip(external_price.filter(|price| *price > 0.))
+            .and_then(|(absolute, price)| {
+                objective::from_f64((absolute * NATIVE_TOKEN_UNIT / price).floor())
+            });
+        absolute.map_or(relative, |absolute| relative.min(absolute))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relative_bps_up_to_the_whole_amount() {
+        assert_eq!(parse_relative_bps("10").unwrap(), 10);
+        assert_eq!(parse_relative_bps("10000").unwrap(), 10_000);
+        assert!(parse_relative_bps("10001").is_err());
+        assert!(parse_relative_bps("-1").is_err());
+    }
+
+    #[test]
+    fn caps_relative_tolerance_at_absolute_one() {
+        let policy = SlippagePolicy {
+            relative_bps: 10,
+            absolute_in_native_token: None,
+        };
+        assert_eq!(policy.tolerance(20_000.into(), Some(1.)), 20.into());
+        assert_eq!(policy.tolerance(999.into(), Some(1.)), 0.into());
+
+        let policy = SlippagePolicy {
+            absolute_in_native_token: Some(1e-17),
+            ..policy
+        };
+        // 10 wei are worth 5 of a token priced at 2.
+        assert_eq!(policy.tolerance(20_000.into(), Some(2.)), 5.into());
+        assert_eq!(policy.tolerance(20_000.into(), Some(0.1)), 20.into());
+        assert_eq!(policy.tolerance(20_000.into(), None), 20.into());
+    }
+}
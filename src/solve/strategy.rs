@@ -0,0 +1,162 @@
+//! Strategies build candidate solutions for an auction. The solver runs the
+//! ones it is configured with and settles the candidate with the highest
+//! objective value among all of them.
+
+use super::{candidate_solutions, Auction, Candidate, Source, CANDIDATES};
+use crate::models::batch_auction_model::{BatchAuctionModel, SettledBatchAuctionModel};
+use crate::models::settlement_contract_data::SignedOrder;
+use crate::solve::risk::RiskLimits;
+use crate::solve::slippage::SlippagePolicy;
+use anyhow::{anyhow, ensure, Result};
+use contracts::MooSettlementContract;
+use tokio::time::Instant;
+use web3::transports::Http;
+use web3::types::H160;
+use web3::Web3;
+
+pub trait SolverStrategy: Send + Sync {
+    /// The name the strategy is selected by.
+    fn name(&self) -> &str;
+
+    /// Candidate solutions for `auction`, none if the strategy can't solve
+    /// it.
+    fn solve(&self, auction: &BatchAuctionModel, context: &ChainContext) -> Vec<CandidateSolution>;
+}
+
+/// What strategies know besides the auction itself.
+pub struct ChainContext<'a> {
+    pub web3: &'a Web3<Http>,
+    pub contract: &'a MooSettlementContract,
+    /// The maker orders that are still valid and haven't been consumed.
+    pub maker_orders: &'a [&'a SignedOrder],
+    /// The gas price in wei, if the driver or the node told us.
+    pub gas_price: Option<f64>,
+    pub slippage: SlippagePolicy,
+    pub risk_limits: RiskLimits,
+    /// `WETH9`, which native ETH gets wrapped into and unwrapped from.
+    pub weth: H160,
+    /// When candidates have to be ready by.
+    pub deadline: Instant,
+}
+
+/// A solution a strategy proposes, ranked by its objective value.
+#[derive(Debug)]
+pub struct CandidateSolution {
+    pub settlement: SettledBatchAuctionModel,
+    pub objective_value: f64,
+    /// The maker orders the settlement fills, to be tracked by the fill
+    /// ledger if it gets proposed.
+    pub maker_orders: Vec<SignedOrder>,
+}
+
+/// The strategies named in a comma separated list. The built-in ones are:
+///
+/// - `moo`: fill user orders from Moo maker orders and liquidity orders,
+/// - `cow`: match user orders against each other,
+/// - `amm`: route user orders through constant product pools,
+/// - `naive`: try every combination of the above.
+///
+/// Joining names with `+` combines them into a single strategy, which first
+/// matches user orders against each other if it includes `cow`, and fills
+/// the rest from the other sources in the order they are named, e.g.
+/// `cow+amm+moo`.
+pub fn from_names(names: &str) -> Result<Vec<Box<dyn SolverStrategy>>> {
+    let strategies = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let strategy: Box<dyn SolverStrategy> = Box::new(builtin(name)?);
+            Ok(strategy)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!strategies.is_empty(), "no strategy named in {names:?}");
+    Ok(strategies)
+}
+
+/// A strategy that builds a candidate for each way of matching and filling
+/// user orders.
+#[derive(Debug)]
+struct Builtin {
+    name: String,
+    candidates: Vec<Candidate>,
+}
+
+impl SolverStrategy for Builtin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn solve(&self, auction: &BatchAuctionModel, context: &ChainContext) -> Vec<CandidateSolution> {
+        match Auction::new(auction, context) {
+            Some(auction) => candidate_solutions(&auction, &self.candidates, context),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn builtin(name: &str) -> Result<Builtin> {
+    if name == "naive" {
+        return Ok(Builtin {
+            name: name.to_string(),
+            candidates: CANDIDATES.to_vec(),
+        });
+    }
+    let mut cow = false;
+    let mut sources = Vec::new();
+    for part in name.split('+') {
+        match part {
+            "cow" if !cow => cow = true,
+            "moo" if !sources.contains(&Source::Makers) => sources.push(Source::Makers),
+            "amm" if !sources.contains(&Source::Amms) => sources.push(Source::Amms),
+            _ => return Err(anyhow!("unknown or repeated strategy {part:?} in {name:?}")),
+        }
+    }
+    let sources: &'static [Source] = match sources.as_slice() {
+        [] => &[],
+        [Source::Makers] => &[Source::Makers],
+        [Source::Amms] => &[Source::Amms],
+        [Source::Makers, Source::Amms] => &[Source::Makers, Source::Amms],
+        [Source::Amms, Source::Makers] => &[Source::Amms, Source::Makers],
+        _ => unreachable!("every source is named at most once"),
+    };
+    ensure!(cow || !sources.is_empty(), "empty strategy {name:?}");
+    Ok(Builtin {
+        name: name.to_string(),
+        candidates: vec![Candidate { cow, sources }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_and_combines_strategies_by_name() {
+        let strategies = from_names("moo, cow+amm+moo,naive").unwrap();
+        let names = strategies
+            .iter()
+            .map(|strategy| strategy.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["moo", "cow+amm+moo", "naive"]);
+
+        let strategy = builtin("cow+amm+moo").unwrap();
+        assert!(strategy.candidates[0].cow);
+        assert_eq!(
+            strategy.candidates[0].sources,
+            [Source::Amms, Source::Makers]
+        );
+        assert_eq!(builtin("naive").unwrap().candidates.len(), CANDIDATES.len());
+
+        assert!(from_names("moo+moo").is_err());
+        assert!(from_names("uniswap").is_err());
+        assert!(from_names("naive+cow").is_err());
+    }
+
+    #[test]
+    fn requires_a_strategy() {
+        assert!(from_names("").is_err());
+        assert!(from_names(",").is_err());
+        assert!(from_names(" , ").is_err());
+    }
+}
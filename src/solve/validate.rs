@@ -0,0 +1,258 @@
+//! Checks a solution against the auction it solves before it gets sent to the
+//! driver, so that a bug in one strategy costs us a solution rather than a
+//! reverted settlement.
+
+use super::{native_token, satisfies_limit_price};
+use crate::models::batch_auction_model::{BatchAuctionModel, SettledBatchAuctionModel};
+use anyhow::{anyhow, ensure, Result};
+use std::collections::HashMap;
+use web3::types::{H160, U256};
+
+/// Fails with the first way `solution` is not a valid solution of `auction`:
+/// - it executes an order the auction doesn't have, that sells native ETH,
+///   which settlements never receive, beyond its limit price,
+///   for more than its amount, for more of its sell token including the fee
+///   than the order signed for or, for fill-or-kill orders, partially,
+/// - a token the executed orders trade has no clearing price,
+/// - an interaction takes an input the approvals or the ETH sent along don't
+///   cover, unless it is a call on the input token itself,
+/// - the settlement pays out more of a token than it takes in. Rounding in
+///   favour of the users leaves each executed order up to one unit short.
+pub fn validate(auction: &BatchAuctionModel, solution: &SettledBatchAuctionModel) -> Result<()> {
+    let mut balances = HashMap::<H160, Balance>::new();
+    for (index, executed) in &solution.orders {
+        let order = auction
+            .orders
+            .get(index)
+            .ok_or_else(|| anyhow!("executed order {index} does not exist"))?;
+        ensure!(
+            order.sell_token != native_token(),
+            "order {index} sells native ETH"
+        );
+        let (sell_amount, buy_amount) = (executed.exec_sell_amount, executed.exec_buy_amount);
+        let (executed_amount, fixed_amount) = if order.is_sell_order {
+            (sell_amount, order.sell_amount)
+        } else {
+            (buy_amount, order.buy_amount)
+        };
+        ensure!(
+            !executed_amount.is_zero() && executed_amount <= fixed_amount,
+            "order {index} executed for {executed_amount} of {fixed_amount}"
+        );
+        ensure!(
+            order.allow_partial_fill || executed_amount == fixed_amount,
+            "fill-or-kill order {index} executed partially"
+        );
+        ensure!(
+            satisfies_limit_price(order, sell_amount, buy_amount),
+            "order {index} executed beyond its limit price"
+        );
+        let fee = executed.exec_fee_amount.unwrap_or(order.fee.amount);
+        ensure!(
+            sell_amount
+                .checked_add(fee)
+                .is_some_and(|total| total <= order.sell_amount.saturating_add(order.fee.amount)),
+            "order {index} sells more including its fee than it signed for"
+        );
+        for token in [order.sell_token, order.buy_token] {
+            ensure!(
+                solution
+                    .prices
+                    .get(&token)
+                    .is_some_and(|price| !price.is_zero()),
+                "token {token:?} of order {index} has no price"
+            );
+        }
+
+        balances
+            .entry(order.sell_token)
+            .or_default()
+            .take(sell_amount)?;
+        balances.entry(order.fee.token).or_default().take(fee)?;
+        let bought = balances.entry(order.buy_token).or_default();
+        bought.pay(buy_amount)?;
+        bought.dust += 1;
+        balances.entry(order.sell_token).or_default().dust += 1;
+    }
+
+    let mut allowances = HashMap::<(H160, H160), U256>::new();
+    for approval in &solution.approvals {
+        let allowance = allowances
+            .entry((approval.token, approval.spender))
+            .or_default();
+        *allowance = allowance
+            .checked_add(approval.amount)
+            .ok_or_else(|| anyhow!("approvals of {:?} overflow", approval.token))?;
+    }
+    for interaction in &solution.interaction_data {
+        let position = interaction.exec_plan.coordinates.position;
+        for input in &interaction.inputs {
+            if input.token == native_token() {
+                ensure!(
+                    interaction.value >= input.amount,
+                    "interaction {position} sends less ETH than its input"
+                );
+            } else if input.token != interaction.target {
+                let allowance = allowances
+                    .get_mut(&(input.token, interaction.target))
+                    .ok_or_else(|| anyhow!("interaction {position} input is not approved"))?;
+                *allowance = allowance
+                    .checked_sub(input.amount)
+                    .ok_or_else(|| anyhow!("interaction {position} input exceeds its approval"))?;
+            }
+            balances.entry(input.token).or_default().pay(input.amount)?;
+        }
+        for output in &interaction.outputs {
+            balances
+                .entry(output.token)
+                .or_default()
+                .take(output.amount)?;
+        }
+    }
+
+    for (token, balance) in balances {
+        ensure!(
+            balance.paid <= balance.taken.saturating_add(balance.dust.into()),
+            "settlement pays out {} of token {token:?} but takes in {}",
+            balance.paid,
+            balance.taken
+        );
+    }
+    Ok(())
+}
+
+/// What the settlement takes in and pays out of a token, and how many units
+/// it may come up short by from rounding.
+#[derive(Default)]
+struct Balance {
+    taken: U256,
+    paid: U256,
+    dust: u64,
+}
+
+impl Balance {
+    fn take(&mut self, amount: U256) -> Result<()> {
+        self.taken = self
+            .taken
+            .checked_add(amount)
+            .ok_or_else(|| anyhow!("amount taken in overflows"))?;
+        Ok(())
+    }
+
+    fn pay(&mut self, amount: U256) -> Result<()> {
+        self.paid = self
+            .paid
+            .checked_add(amount)
+            .ok_or_else(|| anyhow!("amount paid out overflows"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::batch_auction_model::{
+        ApprovalModel, ExecutedOrderModel, ExecutionPlan, InteractionData, TokenAmount,
+    };
+    use crate::solve::fixtures::{order, token};
+
+    /// Sells 100 of token 1 to a maker for 200 of token 2.
+    fn settled() -> (BatchAuctionModel, SettledBatchAuctionModel) {
+        let auction = BatchAuctionModel {
+            orders: [(0, order(100, 150, false))].into(),
+            ..Default::default()
+        };
+        let solution = SettledBatchAuctionModel {
+            orders: [(
+                0,
+                ExecutedOrderModel {
+                    exec_sell_amount: 100.into(),
+                    exec_buy_amount: 200.into(),
+                    exec_fee_amount: None,
+                },
+            )]
+            .into(),
+            prices: [(token(1), 200.into()), (token(2), 100.into())].into(),
+            approvals: vec![ApprovalModel {
+                token: token(1),
+                spender: token(3),
+                amount: 100.into(),
+            }],
+            interaction_data: vec![InteractionData {
+                target: token(3),
+                value: 0.into(),
+                call_data: Vec::new(),
+                exec_plan: ExecutionPlan::default(),
+                inputs: vec![TokenAmount {
+                    amount: 100.into(),
+                    token: token(1),
+                }],
+                outputs: vec![TokenAmount {
+                    amount: 200.into(),
+                    token: token(2),
+                }],
+            }],
+            ..Default::default()
+        };
+        (auction, solution)
+    }
+
+    #[test]
+    fn accepts_valid_solutions() {
+        let (auction, solution) = settled();
+        assert!(validate(&auction, &solution).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_solutions() {
+        let (auction, valid) = settled();
+        let invalid = |change: fn(&mut SettledBatchAuctionModel)| {
+            let mut solution = valid.clone();
+            change(&mut solution);
+            validate(&auction, &solution).is_err()
+        };
+        assert!(invalid(|solution| {
+            let executed = solution.orders.remove(&0).unwrap();
+            solution.orders.insert(1, executed);
+        }));
+        assert!(invalid(|solution| {
+            solution.orders.get_mut(&0).unwrap().exec_buy_amount = 149.into();
+        }));
+        assert!(invalid(|solution| {
+            let executed = solution.orders.get_mut(&0).unwrap();
+            executed.exec_sell_amount = 50.into();
+            executed.exec_buy_amount = 100.into();
+        }));
+        assert!(invalid(|solution| {
+            solution.prices.remove(&token(2));
+        }));
+        // The order signed for 100 plus a fee of 10.
+        assert!(!invalid(|solution| {
+            solution.orders.get_mut(&0).unwrap().exec_fee_amount = Some(10.into());
+        }));
+        assert!(invalid(|solution| {
+            solution.orders.get_mut(&0).unwrap().exec_fee_amount = Some(11.into());
+        }));
+        assert!(invalid(|solution| solution.approvals[0].amount = 99.into()));
+        // One unit short is rounding, two are not.
+        assert!(!invalid(|solution| {
+            solution.orders.get_mut(&0).unwrap().exec_buy_amount = 201.into();
+        }));
+        assert!(invalid(|solution| {
+            solution.orders.get_mut(&0).unwrap().exec_buy_amount = 202.into();
+        }));
+
+        // Even if the ETH the order sells would be there to send along.
+        let mut native_auction = auction.clone();
+        let order = native_auction.orders.get_mut(&0).unwrap();
+        order.sell_token = native_token();
+        order.fee.token = native_token();
+        let mut solution = valid.clone();
+        solution.prices.insert(native_token(), 200.into());
+        solution.approvals.clear();
+        let interaction = &mut solution.interaction_data[0];
+        interaction.value = 100.into();
+        interaction.inputs[0].token = native_token();
+        assert!(validate(&native_auction, &solution).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    panic::{self, PanicInfo},
+    panic::{self, PanicHookInfo},
     thread,
 };
 use tracing_subscriber::fmt::time::ChronoUtc;
@@ -21,7 +21,7 @@ pub fn initialize(env_filter: &str) {
 // Sets a panic hook so panic information is logged in addition to the default panic printer.
 fn set_panic_hook() {
     let default_hook = panic::take_hook();
-    let hook = move |info: &PanicInfo| {
+    let hook = move |info: &PanicHookInfo| {
         let thread = thread::current();
         let thread_name = thread.name().unwrap_or("<unnamed>");
         // It is not possible for our custom hook to print a full backtrace on stable rust. To not